@@ -0,0 +1,159 @@
+//! Runs the whole workflow from lantmäteriet shape files to a garmin `gmapsupp.img`.
+
+use crate::report::report;
+use lantmateriet::coastline::{Coastline, DEFAULT_MIN_AREA};
+use lantmateriet::dem::HeightModel;
+use lantmateriet::hgt::SRTM3;
+use lantmateriet::legend::LEGEND_FILE_NAME;
+use lantmateriet::peak::{Peaks, DEFAULT_MIN_DROP, DEFAULT_RADIUS};
+use lantmateriet::split::{SplitMode, DEFAULT_MAP_ID};
+use lantmateriet::{hgt, legend, split, style, Lantmateriet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Node limit per tile, same as the default in mkgmap's splitter.
+pub const DEFAULT_MAX_NODES: usize = 1_600_000;
+
+pub const DEFAULT_FAMILY_ID: u32 = 909;
+
+/// Folder of the HGT files in the output folder.
+const HGT_FOLDER: &str = "hgt";
+
+pub struct BuildGarmin {
+    pub shape_folder: String,
+    pub output_folder: String,
+    pub config: Option<String>,
+    pub region: Option<String>,
+    pub mkgmap: PathBuf,
+    pub splitter: Option<PathBuf>,
+    pub family_id: u32,
+    /// Extract the coastline and let mkgmap generate the sea.
+    pub coastline: bool,
+    /// Height model for hill shading and summits, none if empty.
+    pub dem: Vec<PathBuf>,
+    pub max_nodes: usize,
+    pub summary: Option<String>,
+    pub strict: bool,
+    /// Folder of the conversion cache, `None` if disabled.
+    pub cache: Option<String>,
+}
+
+impl BuildGarmin {
+    pub fn run(&self) -> io::Result<()> {
+        let output = Path::new(&self.output_folder);
+        fs::create_dir_all(output)?;
+
+        println!("[1/4] Converting {}", &self.shape_folder);
+        let mut lantmateriet = Lantmateriet::open(&self.shape_folder, &self.config, &self.region)?;
+        if let Some(cache) = &self.cache {
+            lantmateriet.set_cache(cache);
+        }
+        if self.coastline {
+            lantmateriet.set_coastline(Coastline {
+                min_area: DEFAULT_MIN_AREA,
+            });
+        }
+        if !self.dem.is_empty() {
+            lantmateriet.set_peaks(Peaks {
+                grids: self.dem.clone(),
+                radius: DEFAULT_RADIUS,
+                min_drop: DEFAULT_MIN_DROP,
+            });
+        }
+        style::write_style(output.join("style"), lantmateriet.config())?;
+        style::write_typ_file(output, lantmateriet.config(), self.family_id)?;
+        let config = lantmateriet.config();
+        legend::write_legend(
+            output.join(LEGEND_FILE_NAME),
+            config,
+            config.language.as_deref(),
+        )?;
+        let (osm, summary) = lantmateriet.read_with_summary()?;
+        report(&summary, &self.summary, self.strict)?;
+        if !self.dem.is_empty() {
+            let files = hgt::write(
+                output.join(HGT_FOLDER),
+                &HeightModel::open(&self.dem)?,
+                SRTM3,
+            )?;
+            println!("Wrote {} height files", files.len());
+        }
+
+        println!("[2/4] Splitting into tiles");
+        if let Some(splitter) = &self.splitter {
+            let map = output.join("map.pbf");
+            lantmateriet::pbf::write(&map, &osm)?;
+            java(
+                splitter,
+                output,
+                &[
+                    format!("--max-nodes={}", self.max_nodes),
+                    format!("--mapid={}", DEFAULT_MAP_ID),
+                    "map.pbf".to_owned(),
+                ],
+            )?;
+            fs::remove_file(map)?;
+        } else {
+            let mode = SplitMode::Adaptive {
+                max_nodes: self.max_nodes,
+            };
+            let tiles = split::split(&osm, mode, DEFAULT_MAP_ID)?;
+            split::write_tiles(output, &tiles, "Lantmateriet")?;
+        }
+
+        println!("[3/4] Compiling TYP file");
+        java(
+            &self.mkgmap,
+            output,
+            &[
+                format!("--family-id={}", self.family_id),
+                style::TYP_FILE_NAME.to_owned(),
+            ],
+        )?;
+
+        println!("[4/4] Building gmapsupp.img");
+        let mut args = vec![
+            "--gmapsupp".to_owned(),
+            format!("--family-id={}", self.family_id),
+            "--style-file=style".to_owned(),
+        ];
+        if !self.dem.is_empty() {
+            args.push(format!("--dem={}", HGT_FOLDER));
+        }
+        if self.coastline {
+            args.push("--generate-sea=multipolygon".to_owned());
+        }
+        args.extend_from_slice(&[
+            "-c".to_owned(),
+            "template.args".to_owned(),
+            "typfile.typ".to_owned(),
+        ]);
+        java(&self.mkgmap, output, &args)?;
+
+        println!("Done, {:?}", output.join("gmapsupp.img"));
+        Ok(())
+    }
+}
+
+/// Runs `java -jar <jar> <args>` in `folder` and fails if the exit status isn't success.
+fn java(jar: &Path, folder: &Path, args: &[String]) -> io::Result<()> {
+    let jar = fs::canonicalize(jar)
+        .map_err(|e| io::Error::new(e.kind(), format!("Could not find jar {:?}: {}", jar, e)))?;
+
+    let status = Command::new("java")
+        .arg("-jar")
+        .arg(&jar)
+        .args(args)
+        .current_dir(folder)
+        .status()?;
+
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "{:?} failed with {}",
+            jar, status
+        )));
+    }
+    Ok(())
+}
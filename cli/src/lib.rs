@@ -1,13 +1,103 @@
 extern crate clap;
 
-use clap::{App, Arg};
+pub mod garmin;
+pub mod report;
 
-pub struct Cli {
+use crate::garmin::{BuildGarmin, DEFAULT_FAMILY_ID, DEFAULT_MAX_NODES};
+use clap::{value_t, App, AppSettings, Arg, ArgGroup, ArgMatches, SubCommand};
+use lantmateriet::catalogue::CatalogueFormat;
+use lantmateriet::coastline::{Coastline, DEFAULT_MIN_AREA};
+use lantmateriet::contour::{Contours, DEFAULT_INTERVAL, DEFAULT_SMOOTHING};
+use lantmateriet::dump::DumpFormat;
+use lantmateriet::hgt::{SRTM1, SRTM3};
+use lantmateriet::peak::{Peaks, DEFAULT_MIN_DROP, DEFAULT_RADIUS};
+use lantmateriet::process::RawAttributes;
+use lantmateriet::split::{SplitMode, DEFAULT_MAP_ID, MAX_MAP_ID};
+use lantmateriet::tiles::{DEFAULT_MAX_ZOOM, DEFAULT_MIN_ZOOM};
+use lantmateriet::Lantmateriet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub enum Cli {
+    Convert(Convert),
+    Info(Source),
+    Dump(Dump),
+    Validate(Source),
+    Style(Style),
+    Config(Option<String>),
+    Catalogue(Catalogue),
+    GeoPackage(GeoPackage),
+    Hgt(Hgt),
+    BuildGarmin(BuildGarmin),
+}
+
+/// The shape folder and config the subcommands read from.
+pub struct Source {
+    pub shape_folder: String,
+    pub config: Option<String>,
+    pub region: Option<String>,
+}
+
+pub struct Convert {
     pub shape_folder: String,
     pub output: String,
     pub output_format: Option<String>,
     pub config: Option<String>,
     pub region: Option<String>,
+    pub split: Option<SplitMode>,
+    pub map_id: u32,
+    /// Zoom levels of vector tiles.
+    pub min_zoom: u8,
+    pub max_zoom: u8,
+    pub style: Option<String>,
+    pub summary: Option<String>,
+    pub strict: bool,
+    /// Folder of the conversion cache, `None` if disabled.
+    pub cache: Option<String>,
+    pub raw_attributes: RawAttributes,
+    /// Height model to generate contours from, `None` to read them from the shape files.
+    pub contours: Option<Contours>,
+    /// Height model to find summits in, `None` to leave out the peak layer.
+    pub peaks: Option<Peaks>,
+    /// Coastline extraction, `None` to leave out the coastline layer.
+    pub coastline: Option<Coastline>,
+}
+
+pub struct Dump {
+    pub source: Source,
+    pub layer: Option<String>,
+    pub format: DumpFormat,
+}
+
+pub struct Catalogue {
+    pub output: Option<String>,
+    pub config: Option<String>,
+    pub language: Option<String>,
+    pub format: CatalogueFormat,
+}
+
+pub struct GeoPackage {
+    pub source: Source,
+    pub output: String,
+}
+
+pub struct Hgt {
+    pub grids: Vec<PathBuf>,
+    pub output: String,
+    /// Samples per side of a file, see `hgt`.
+    pub samples: usize,
+}
+
+pub struct Style {
+    pub output: String,
+    pub config: Option<String>,
+    pub family_id: u32,
+}
+
+impl Source {
+    pub fn open(&self) -> io::Result<Lantmateriet> {
+        Lantmateriet::open(&self.shape_folder, &self.config, &self.region)
+    }
 }
 
 impl Cli {
@@ -15,54 +105,634 @@ impl Cli {
         let matches = App::new("Lantmäteriet")
             .version(env!("CARGO_PKG_VERSION"))
             .about("Converts lantmäteriet shape files to osm formats.")
-            .arg(
-                Arg::with_name("SHAPE_FOLDER")
-                    .help("Base folder. E.g. ./terrang/21/")
-                    .value_name("SHAPE_FOLDER")
-                    .required(true)
-                    .help("Sets a custom config file"),
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                SubCommand::with_name("convert")
+                    .about("Converts shape files to osm, o5m, pbf or vector tiles.")
+                    .arg(shape_folder_arg())
+                    .arg(
+                        Arg::with_name("OUTPUT")
+                            .help("Output file")
+                            .value_name("FILE")
+                            .short("o")
+                            .long("output")
+                            .required(true)
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("OUTPUT_FORMAT")
+                            .help("Force output format, osm, o5m, pbf, mbtiles or pmtiles. Default is based on output file name")
+                            .value_name("OUTPUT_FORMAT")
+                            .short("f")
+                            .long("format")
+                            .takes_value(true),
+                    )
+                    .arg(region_arg())
+                    .arg(config_arg())
+                    .arg(
+                        Arg::with_name("SPLIT_GRID")
+                            .help("Split output into tiles of this size in degrees. Output is a folder")
+                            .value_name("DEGREES")
+                            .long("split-grid")
+                            .takes_value(true)
+                            .validator(|v| match v.parse::<f64>() {
+                                Ok(degrees) if degrees > 0.0 && degrees.is_finite() => Ok(()),
+                                _ => Err("must be a positive number of degrees".to_owned()),
+                            })
+                            .conflicts_with("MAX_NODES"),
+                    )
+                    .arg(
+                        Arg::with_name("MAX_NODES")
+                            .help("Split output into tiles with at most this many nodes. Output is a folder")
+                            .value_name("NODES")
+                            .long("max-nodes")
+                            .takes_value(true)
+                            .validator(positive_count),
+                    )
+                    .arg(
+                        Arg::with_name("MAP_ID")
+                            .help("Map name of the first tile when splitting")
+                            .value_name("MAP_ID")
+                            .long("mapid")
+                            .takes_value(true)
+                            .validator(|v| match v.parse::<u32>() {
+                                Ok(id) if id > 0 && id <= MAX_MAP_ID => Ok(()),
+                                _ => Err(format!("must be a map name from 1 to {}", MAX_MAP_ID)),
+                            }),
+                    )
+                    .arg(
+                        Arg::with_name("MIN_ZOOM")
+                            .help("Lowest zoom level of vector tiles, default 0")
+                            .value_name("ZOOM")
+                            .long("min-zoom")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("MAX_ZOOM")
+                            .help("Highest zoom level of vector tiles, default 14")
+                            .value_name("ZOOM")
+                            .long("max-zoom")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("STYLE")
+                            .help("Also write the mkgmap style and TYP file generated from the config")
+                            .value_name("FOLDER")
+                            .long("style")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("DEM")
+                            .help("Height model files for --contours and --peaks, .asc or .tif")
+                            .value_name("FILE")
+                            .long("dem")
+                            .takes_value(true)
+                            .multiple(true)
+                            .requires("DEM_OUTPUT"),
+                    )
+                    .arg(
+                        Arg::with_name("CONTOURS")
+                            .help("Replace the contours of the oh layer with contours of the height model")
+                            .long("contours")
+                            .requires("DEM"),
+                    )
+                    .arg(
+                        Arg::with_name("PEAKS")
+                            .help("Add the summits of the height model as the peak layer")
+                            .long("peaks")
+                            .requires("DEM"),
+                    )
+                    .group(ArgGroup::with_name("DEM_OUTPUT").args(&["CONTOURS", "PEAKS"]).multiple(true))
+                    .arg(
+                        Arg::with_name("CONTOUR_INTERVAL")
+                            .help("Meters between generated contours, default 5")
+                            .value_name("METERS")
+                            .long("contour-interval")
+                            .takes_value(true)
+                            .validator(|v| match v.parse::<f64>() {
+                                Ok(meters) if meters > 0.0 => Ok(()),
+                                _ => Err("must be a positive number of meters".to_owned()),
+                            }),
+                    )
+                    .arg(
+                        Arg::with_name("CONTOUR_SMOOTHING")
+                            .help("Smoothing passes of generated contours, default 2")
+                            .value_name("PASSES")
+                            .long("contour-smoothing")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("PEAK_RADIUS")
+                            .help("Meters a summit must be the highest point within, default 250")
+                            .value_name("METERS")
+                            .long("peak-radius")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("PEAK_DROP")
+                            .help("Meters a summit must rise above the lowest point within the radius, default 10")
+                            .value_name("METERS")
+                            .long("peak-drop")
+                            .takes_value(true),
+                    )
+                    .arg(coastline_arg())
+                    .arg(
+                        Arg::with_name("SEA_MIN_AREA")
+                            .help("Square kilometers a water polygon must cover to be sea, default 1")
+                            .value_name("KM2")
+                            .long("sea-min-area")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("RAW_ATTRIBUTES")
+                            .help("Add all attributes as lm: tags, and records of unknown kkods tagged lm:unmapped=yes")
+                            .long("raw-attributes"),
+                    )
+                    .arg(
+                        Arg::with_name("RAW_ONLY")
+                            .help("Like --raw-attributes but without the tags from the config")
+                            .long("raw-only"),
+                    )
+                    .arg(summary_arg())
+                    .arg(strict_arg())
+                    .arg(cache_arg())
+                    .arg(no_cache_arg()),
+            )
+            .subcommand(
+                SubCommand::with_name("info")
+                    .about("Lists the layers, record counts and bounds of a shape folder.")
+                    .arg(shape_folder_arg())
+                    .arg(region_arg())
+                    .arg(config_arg()),
+            )
+            .subcommand(
+                SubCommand::with_name("dump")
+                    .about("Prints the records of a shape folder with all attributes.")
+                    .arg(shape_folder_arg())
+                    .arg(
+                        Arg::with_name("LAYER")
+                            .help("Only dump this layer, e.g. my")
+                            .value_name("LAYER")
+                            .short("l")
+                            .long("layer")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("JSON")
+                            .help("One json object per record instead of text")
+                            .long("json"),
+                    )
+                    .arg(region_arg())
+                    .arg(config_arg()),
+            )
+            .subcommand(
+                SubCommand::with_name("validate")
+                    .about("Checks a shape folder against the config.")
+                    .arg(shape_folder_arg())
+                    .arg(region_arg())
+                    .arg(config_arg()),
+            )
+            .subcommand(
+                SubCommand::with_name("style")
+                    .about("Writes the mkgmap style and TYP file generated from the config.")
+                    .arg(
+                        Arg::with_name("OUTPUT")
+                            .help("Output folder")
+                            .value_name("FOLDER")
+                            .short("o")
+                            .long("output")
+                            .required(true)
+                            .takes_value(true),
+                    )
+                    .arg(family_id_arg())
+                    .arg(config_arg()),
             )
-            .arg(
-                Arg::with_name("OUTPUT")
-                    .help("Output file")
-                    .value_name("FILE")
-                    .short("o")
-                    .long("output")
-                    .required(true)
-                    .takes_value(true),
+            .subcommand(
+                SubCommand::with_name("config")
+                    .about("Prints the effective config, with everything it extends merged in.")
+                    .arg(config_arg()),
             )
-            .arg(
-                Arg::with_name("OUTPUT_FORMAT")
-                    .help("Force output format, osm or o5m. Default is based on output file name")
-                    .value_name("OUTPUT_FORMAT")
-                    .short("f")
-                    .long("format")
-                    .takes_value(true),
+            .subcommand(
+                SubCommand::with_name("catalogue")
+                    .about("Writes a feature catalogue of the config, the tags and garmin type of every kkod.")
+                    .arg(
+                        Arg::with_name("OUTPUT")
+                            .help("Output file. Html if it ends with .html, markdown otherwise. Default is stdout")
+                            .value_name("FILE")
+                            .short("o")
+                            .long("output")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("HTML")
+                            .help("Write html instead of markdown")
+                            .long("html"),
+                    )
+                    .arg(
+                        Arg::with_name("LANGUAGE")
+                            .help("Language of the titles, e.g. en. Default is the language in the config, or swedish")
+                            .value_name("LANGUAGE")
+                            .short("l")
+                            .long("language")
+                            .takes_value(true),
+                    )
+                    .arg(config_arg()),
             )
-            .arg(
-                Arg::with_name("REGION")
-                    .help("Force region. Default is identified by folder content")
-                    .value_name("REGION")
-                    .short("r")
-                    .long("region")
-                    .takes_value(true),
+            .subcommand(
+                SubCommand::with_name("geopackage")
+                    .about("Writes the shape files as a GeoPackage with one table per layer.")
+                    .arg(shape_folder_arg())
+                    .arg(
+                        Arg::with_name("OUTPUT")
+                            .help("Output file, e.g. terrang.gpkg")
+                            .value_name("FILE")
+                            .short("o")
+                            .long("output")
+                            .required(true)
+                            .takes_value(true),
+                    )
+                    .arg(region_arg())
+                    .arg(config_arg()),
             )
-            .arg(
-                Arg::with_name("CONFIG")
-                    .help("Custom config file")
-                    .short("c")
-                    .long("config")
-                    .value_name("FILE")
-                    .takes_value(true),
+            .subcommand(
+                SubCommand::with_name("hgt")
+                    .about("Writes HGT files of a height model for mkgmap's --dem option.")
+                    .arg(
+                        Arg::with_name("DEM")
+                            .help("Height model files, .asc or .tif")
+                            .value_name("FILE")
+                            .required(true)
+                            .multiple(true),
+                    )
+                    .arg(
+                        Arg::with_name("OUTPUT")
+                            .help("Output folder")
+                            .value_name("FOLDER")
+                            .short("o")
+                            .long("output")
+                            .required(true)
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("RESOLUTION")
+                            .help("Arc seconds between heights, 1 or 3. Default 3")
+                            .value_name("SECONDS")
+                            .long("resolution")
+                            .possible_values(&["1", "3"])
+                            .takes_value(true),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("build-garmin")
+                    .about("Converts, splits and runs mkgmap to build a gmapsupp.img.")
+                    .arg(shape_folder_arg())
+                    .arg(
+                        Arg::with_name("OUTPUT")
+                            .help("Output folder, will contain gmapsupp.img and all intermediate files")
+                            .value_name("FOLDER")
+                            .short("o")
+                            .long("output")
+                            .required(true)
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("MKGMAP")
+                            .help("Path to mkgmap.jar")
+                            .value_name("JAR")
+                            .long("mkgmap")
+                            .required(true)
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("SPLITTER")
+                            .help("Path to splitter.jar. Default is to use the built-in splitter")
+                            .value_name("JAR")
+                            .long("splitter")
+                            .takes_value(true),
+                    )
+                    .arg(family_id_arg())
+                    .arg(coastline_arg())
+                    .arg(
+                        Arg::with_name("DEM")
+                            .help("Height model files, .asc or .tif, for hill shading, elevation profiles and summits")
+                            .value_name("FILE")
+                            .long("dem")
+                            .takes_value(true)
+                            .multiple(true),
+                    )
+                    .arg(
+                        Arg::with_name("MAX_NODES")
+                            .help("Max number of nodes per tile")
+                            .value_name("NODES")
+                            .long("max-nodes")
+                            .takes_value(true)
+                            .validator(positive_count),
+                    )
+                    .arg(summary_arg())
+                    .arg(strict_arg())
+                    .arg(cache_arg())
+                    .arg(no_cache_arg())
+                    .arg(region_arg())
+                    .arg(config_arg()),
             )
             .get_matches();
 
-        Cli {
+        match matches.subcommand() {
+            ("convert", Some(matches)) => Cli::Convert(Self::convert(matches)),
+            ("info", Some(matches)) => Cli::Info(Self::source(matches)),
+            ("dump", Some(matches)) => Cli::Dump(Self::dump(matches)),
+            ("validate", Some(matches)) => Cli::Validate(Self::source(matches)),
+            ("style", Some(matches)) => Cli::Style(Self::style(matches)),
+            ("config", Some(matches)) => {
+                Cli::Config(matches.value_of("CONFIG").map(|s| s.to_owned()))
+            }
+            ("catalogue", Some(matches)) => Cli::Catalogue(Self::catalogue(matches)),
+            ("geopackage", Some(matches)) => Cli::GeoPackage(GeoPackage {
+                source: Self::source(matches),
+                output: matches.value_of("OUTPUT").unwrap().to_owned(),
+            }),
+            ("hgt", Some(matches)) => Cli::Hgt(Hgt {
+                grids: matches
+                    .values_of("DEM")
+                    .unwrap()
+                    .map(PathBuf::from)
+                    .collect(),
+                output: matches.value_of("OUTPUT").unwrap().to_owned(),
+                samples: match matches.value_of("RESOLUTION") {
+                    Some("1") => SRTM1,
+                    _ => SRTM3,
+                },
+            }),
+            ("build-garmin", Some(matches)) => Cli::BuildGarmin(Self::build_garmin(matches)),
+            _ => unreachable!("Subcommand is required"),
+        }
+    }
+
+    fn source(matches: &ArgMatches) -> Source {
+        Source {
             shape_folder: matches.value_of("SHAPE_FOLDER").unwrap().to_owned(),
-            output: matches.value_of("OUTPUT").unwrap().to_owned(),
+            config: matches.value_of("CONFIG").map(|s| s.to_owned()),
+            region: matches.value_of("REGION").map(|s| s.to_owned()),
+        }
+    }
+
+    fn convert(matches: &ArgMatches) -> Convert {
+        let split = if matches.is_present("SPLIT_GRID") {
+            let degrees = value_t!(matches, "SPLIT_GRID", f64).unwrap_or_else(|e| e.exit());
+            Some(SplitMode::grid(degrees))
+        } else if matches.is_present("MAX_NODES") {
+            let max_nodes = value_t!(matches, "MAX_NODES", usize).unwrap_or_else(|e| e.exit());
+            Some(SplitMode::Adaptive { max_nodes })
+        } else {
+            None
+        };
+
+        let map_id = if matches.is_present("MAP_ID") {
+            value_t!(matches, "MAP_ID", u32).unwrap_or_else(|e| e.exit())
+        } else {
+            DEFAULT_MAP_ID
+        };
+
+        let zoom = |name: &str, default: u8| {
+            if matches.is_present(name) {
+                value_t!(matches, name, u8).unwrap_or_else(|e| e.exit())
+            } else {
+                default
+            }
+        };
+
+        let number = |name: &str, default: f64| {
+            if matches.is_present(name) {
+                value_t!(matches, name, f64).unwrap_or_else(|e| e.exit())
+            } else {
+                default
+            }
+        };
+
+        let dem: Vec<PathBuf> = matches
+            .values_of("DEM")
+            .map_or_else(Vec::new, |grids| grids.map(PathBuf::from).collect());
+        let contours = if matches.is_present("CONTOURS") {
+            Some(Contours {
+                grids: dem.clone(),
+                interval: number("CONTOUR_INTERVAL", DEFAULT_INTERVAL),
+                smoothing: if matches.is_present("CONTOUR_SMOOTHING") {
+                    value_t!(matches, "CONTOUR_SMOOTHING", usize).unwrap_or_else(|e| e.exit())
+                } else {
+                    DEFAULT_SMOOTHING
+                },
+            })
+        } else {
+            None
+        };
+        let peaks = if matches.is_present("PEAKS") {
+            Some(Peaks {
+                grids: dem,
+                radius: number("PEAK_RADIUS", DEFAULT_RADIUS),
+                min_drop: number("PEAK_DROP", DEFAULT_MIN_DROP),
+            })
+        } else {
+            None
+        };
+
+        let coastline = if matches.is_present("COASTLINE") {
+            Some(Coastline {
+                min_area: number("SEA_MIN_AREA", DEFAULT_MIN_AREA),
+            })
+        } else {
+            None
+        };
+
+        let raw_attributes = if matches.is_present("RAW_ONLY") {
+            RawAttributes::Only
+        } else if matches.is_present("RAW_ATTRIBUTES") {
+            RawAttributes::WithTags
+        } else {
+            RawAttributes::Off
+        };
+
+        let output = matches.value_of("OUTPUT").unwrap().to_owned();
+        Convert {
+            shape_folder: matches.value_of("SHAPE_FOLDER").unwrap().to_owned(),
+            cache: Self::cache(matches, format!("{}.cache", output)),
+            output,
             output_format: matches.value_of("OUTPUT_FORMAT").map(|s| s.to_owned()),
             config: matches.value_of("CONFIG").map(|s| s.to_owned()),
             region: matches.value_of("REGION").map(|s| s.to_owned()),
+            split,
+            map_id,
+            min_zoom: zoom("MIN_ZOOM", DEFAULT_MIN_ZOOM),
+            max_zoom: zoom("MAX_ZOOM", DEFAULT_MAX_ZOOM),
+            style: matches.value_of("STYLE").map(|s| s.to_owned()),
+            summary: matches.value_of("SUMMARY").map(|s| s.to_owned()),
+            strict: matches.is_present("STRICT"),
+            raw_attributes,
+            contours,
+            peaks,
+            coastline,
         }
     }
+
+    fn dump(matches: &ArgMatches) -> Dump {
+        let format = if matches.is_present("JSON") {
+            DumpFormat::Json
+        } else {
+            DumpFormat::Text
+        };
+
+        Dump {
+            source: Self::source(matches),
+            layer: matches.value_of("LAYER").map(|s| s.to_owned()),
+            format,
+        }
+    }
+
+    fn catalogue(matches: &ArgMatches) -> Catalogue {
+        let output = matches.value_of("OUTPUT").map(|s| s.to_owned());
+        let html =
+            matches.is_present("HTML") || output.as_ref().is_some_and(|o| o.ends_with(".html"));
+        Catalogue {
+            output,
+            config: matches.value_of("CONFIG").map(|s| s.to_owned()),
+            language: matches.value_of("LANGUAGE").map(|s| s.to_owned()),
+            format: if html {
+                CatalogueFormat::Html
+            } else {
+                CatalogueFormat::Markdown
+            },
+        }
+    }
+
+    fn style(matches: &ArgMatches) -> Style {
+        Style {
+            output: matches.value_of("OUTPUT").unwrap().to_owned(),
+            config: matches.value_of("CONFIG").map(|s| s.to_owned()),
+            family_id: Self::family_id(matches),
+        }
+    }
+
+    fn build_garmin(matches: &ArgMatches) -> BuildGarmin {
+        let max_nodes = if matches.is_present("MAX_NODES") {
+            value_t!(matches, "MAX_NODES", usize).unwrap_or_else(|e| e.exit())
+        } else {
+            DEFAULT_MAX_NODES
+        };
+
+        let output_folder = matches.value_of("OUTPUT").unwrap().to_owned();
+        BuildGarmin {
+            shape_folder: matches.value_of("SHAPE_FOLDER").unwrap().to_owned(),
+            cache: Self::cache(
+                matches,
+                Path::new(&output_folder)
+                    .join("cache")
+                    .to_string_lossy()
+                    .into_owned(),
+            ),
+            output_folder,
+            config: matches.value_of("CONFIG").map(|s| s.to_owned()),
+            region: matches.value_of("REGION").map(|s| s.to_owned()),
+            mkgmap: PathBuf::from(matches.value_of("MKGMAP").unwrap()),
+            splitter: matches.value_of("SPLITTER").map(PathBuf::from),
+            family_id: Self::family_id(matches),
+            coastline: matches.is_present("COASTLINE"),
+            dem: matches
+                .values_of("DEM")
+                .map_or_else(Vec::new, |files| files.map(PathBuf::from).collect()),
+            max_nodes,
+            summary: matches.value_of("SUMMARY").map(|s| s.to_owned()),
+            strict: matches.is_present("STRICT"),
+        }
+    }
+
+    /// The cache folder, or `default` if not given. `None` with `--no-cache`.
+    fn cache(matches: &ArgMatches, default: String) -> Option<String> {
+        if matches.is_present("NO_CACHE") {
+            None
+        } else {
+            Some(matches.value_of("CACHE").map_or(default, |s| s.to_owned()))
+        }
+    }
+
+    fn family_id(matches: &ArgMatches) -> u32 {
+        if matches.is_present("FAMILY_ID") {
+            value_t!(matches, "FAMILY_ID", u32).unwrap_or_else(|e| e.exit())
+        } else {
+            DEFAULT_FAMILY_ID
+        }
+    }
+}
+
+fn shape_folder_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("SHAPE_FOLDER")
+        .help("Base folder or zip archive. E.g. ./terrang/21/")
+        .value_name("SHAPE_FOLDER")
+        .required(true)
+}
+
+fn region_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("REGION")
+        .help("Region to use, required if the folder has several. Default is identified by folder content")
+        .value_name("REGION")
+        .short("r")
+        .long("region")
+        .takes_value(true)
+}
+
+fn config_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("CONFIG")
+        .help("Custom config file")
+        .short("c")
+        .long("config")
+        .value_name("FILE")
+        .takes_value(true)
+}
+
+fn family_id_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("FAMILY_ID")
+        .help("Garmin family id of the map")
+        .value_name("ID")
+        .long("family-id")
+        .takes_value(true)
+}
+
+fn summary_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("SUMMARY")
+        .help("Write the conversion summary as json to this file")
+        .value_name("FILE")
+        .long("summary")
+        .takes_value(true)
+}
+
+fn strict_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("STRICT")
+        .help("Fail on kkods that are not in the config")
+        .long("strict")
+}
+
+fn positive_count(v: String) -> Result<(), String> {
+    match v.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(()),
+        _ => Err("must be a positive number".to_owned()),
+    }
+}
+
+fn coastline_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("COASTLINE")
+        .help("Extract the coastline of the sea from the my layer, for mkgmap's --generate-sea")
+        .long("coastline")
+}
+
+fn cache_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("CACHE")
+        .help("Folder of the cache of converted layers. Default is next to the output")
+        .value_name("FOLDER")
+        .long("cache")
+        .takes_value(true)
+}
+
+fn no_cache_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("NO_CACHE")
+        .help("Convert every layer, without reading or writing the cache")
+        .long("no-cache")
+        .conflicts_with("CACHE")
 }
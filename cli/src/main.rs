@@ -1,7 +1,19 @@
 extern crate vadeen_osm;
 
-use cli::Cli;
-use lantmateriet::Lantmateriet;
+use cli::garmin::DEFAULT_FAMILY_ID;
+use cli::report::report;
+use cli::{Catalogue, Cli, Convert, Dump, GeoPackage, Hgt, Source, Style};
+use lantmateriet::dem::HeightModel;
+use lantmateriet::legend::LEGEND_FILE_NAME;
+use lantmateriet::tiles::{TileFormat, VectorTiles};
+use lantmateriet::validate::Severity;
+use lantmateriet::{
+    catalogue, config, dump, gpkg, hgt, info, legend, pbf, split, style, validate, Lantmateriet,
+};
+use std::fs;
+use std::io;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
 use vadeen_osm::osm_io::error::Error;
 use vadeen_osm::osm_io::write;
 
@@ -10,18 +22,194 @@ fn main() {
         Ok(_) => (),
         Err(e) => {
             eprintln!("{}", e);
+            std::process::exit(1);
         }
     }
 }
 
 fn run() -> std::result::Result<(), Error> {
-    let cli = Cli::run();
+    match Cli::run() {
+        Cli::Convert(convert) => run_convert(convert),
+        Cli::Info(source) => Ok(run_info(source)?),
+        Cli::Dump(dump) => Ok(run_dump(dump)?),
+        Cli::Validate(source) => Ok(run_validate(source)?),
+        Cli::Style(style) => Ok(run_style(style)?),
+        Cli::Config(config) => {
+            print!("{}", Lantmateriet::resolve_config(&config)?);
+            Ok(())
+        }
+        Cli::Catalogue(catalogue) => Ok(run_catalogue(catalogue)?),
+        Cli::GeoPackage(geopackage) => Ok(run_geopackage(geopackage)?),
+        Cli::Hgt(cli) => Ok(run_hgt(cli)?),
+        Cli::BuildGarmin(build) => Ok(build.run()?),
+    }
+}
+
+fn run_convert(cli: Convert) -> std::result::Result<(), Error> {
+    let mut lantmateriet = Lantmateriet::open(&cli.shape_folder, &cli.config, &cli.region)?;
+    if let Some(cache) = &cli.cache {
+        lantmateriet.set_cache(cache);
+    }
+    lantmateriet.set_raw_attributes(cli.raw_attributes);
+    if let Some(contours) = &cli.contours {
+        lantmateriet.set_contours(contours.clone());
+    }
+    if let Some(peaks) = &cli.peaks {
+        lantmateriet.set_peaks(peaks.clone());
+    }
+    if let Some(coastline) = &cli.coastline {
+        lantmateriet.set_coastline(coastline.clone());
+    }
+    if let Some(folder) = &cli.style {
+        println!("Writing style to {}", folder);
+        style::write_style(folder, lantmateriet.config())?;
+        style::write_typ_file(folder, lantmateriet.config(), DEFAULT_FAMILY_ID)?;
+        let config = lantmateriet.config();
+        legend::write_legend(
+            Path::new(folder).join(LEGEND_FILE_NAME),
+            config,
+            config.language.as_deref(),
+        )?;
+    }
 
-    let lantmateriet = Lantmateriet::open(&cli.shape_folder, &cli.config, &cli.region)?;
-    let osm = lantmateriet.read()?;
+    if let Some(format) = tile_format(&cli) {
+        let mut tiles = VectorTiles::new(
+            "Lantmateriet",
+            lantmateriet.config(),
+            cli.min_zoom,
+            cli.max_zoom,
+        );
+        let summary = lantmateriet.convert(&mut [], &mut tiles)?;
+        report(&summary, &cli.summary, cli.strict)?;
+
+        println!("Writing {}", &cli.output);
+        let count = tiles.write(&cli.output, format)?;
+        println!("Wrote {} tiles", count);
+        return Ok(());
+    }
+
+    let (osm, summary) = lantmateriet.read_with_summary()?;
+    report(&summary, &cli.summary, cli.strict)?;
+
+    if let Some(mode) = cli.split {
+        println!("Splitting into {}", &cli.output);
+        let tiles = split::split(&osm, mode, cli.map_id)?;
+        split::write_tiles(&cli.output, &tiles, "Lantmateriet")?;
+        println!("Wrote {} tiles", tiles.len());
+        return Ok(());
+    }
 
     println!("Writing {}", &cli.output);
-    write(cli.output, &osm)?;
+    if is_pbf(&cli) {
+        pbf::write(cli.output, &osm)?;
+    } else {
+        write(cli.output, &osm)?;
+    }
+
+    Ok(())
+}
 
+fn run_info(source: Source) -> io::Result<()> {
+    let lantmateriet = source.open()?;
+    print!("{}", info::info(&lantmateriet)?);
     Ok(())
 }
+
+fn run_dump(cli: Dump) -> io::Result<()> {
+    let lantmateriet = cli.source.open()?;
+    let stdout = io::stdout();
+    let mut writer = io::BufWriter::new(stdout.lock());
+    dump::dump(&lantmateriet, cli.layer.as_deref(), cli.format, &mut writer)?;
+    writer.flush()
+}
+
+fn run_validate(source: Source) -> io::Result<()> {
+    let config = Lantmateriet::config_source(&source.config)?;
+    let mut problems = config::validate::validate(&config);
+
+    let config_errors = problems.iter().any(|p| p.severity == Severity::Error);
+    if !config_errors {
+        let lantmateriet = source.open()?;
+        problems.extend(validate::validate(&lantmateriet)?);
+    }
+
+    let config_name = source.config.as_deref().unwrap_or("<default config>");
+    for problem in &problems {
+        if problem.position.is_some() {
+            println!("{}:{}", config_name, problem);
+        } else {
+            println!("{}", problem);
+        }
+    }
+
+    let errors = problems
+        .iter()
+        .filter(|p| p.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("Validation failed with {} errors", errors),
+        ));
+    }
+    println!("Ok, {} warnings", problems.len());
+    Ok(())
+}
+
+fn run_style(cli: Style) -> io::Result<()> {
+    let config = Lantmateriet::parse_config(&cli.config)?;
+    style::write_style(&cli.output, &config)?;
+    style::write_typ_file(&cli.output, &config, cli.family_id)?;
+    legend::write_legend(
+        Path::new(&cli.output).join(LEGEND_FILE_NAME),
+        &config,
+        config.language.as_deref(),
+    )?;
+    println!("Wrote style to {}", &cli.output);
+    Ok(())
+}
+
+fn run_catalogue(cli: Catalogue) -> io::Result<()> {
+    let config = Lantmateriet::parse_config(&cli.config)?;
+    let language = cli.language.as_deref().or(config.language.as_deref());
+    let catalogue = catalogue::catalogue(&config, language, cli.format);
+    match &cli.output {
+        Some(output) => {
+            fs::write(output, catalogue)?;
+            println!("Wrote catalogue to {}", output);
+        }
+        None => print!("{}", catalogue),
+    }
+    Ok(())
+}
+
+fn run_geopackage(cli: GeoPackage) -> io::Result<()> {
+    let lantmateriet = cli.source.open()?;
+    println!("Writing {}", &cli.output);
+    for table in gpkg::write(&cli.output, &lantmateriet)? {
+        println!("{}: {} records", table.name, table.records);
+    }
+    Ok(())
+}
+
+fn run_hgt(cli: Hgt) -> io::Result<()> {
+    let model = HeightModel::open(&cli.grids)?;
+    for file in hgt::write(&cli.output, &model, cli.samples)? {
+        println!("Wrote {}", file.display());
+    }
+    Ok(())
+}
+
+fn tile_format(cli: &Convert) -> Option<TileFormat> {
+    match &cli.output_format {
+        Some(format) => TileFormat::from_name(format),
+        None => TileFormat::from_path(&cli.output),
+    }
+}
+
+fn is_pbf(cli: &Convert) -> bool {
+    match &cli.output_format {
+        Some(format) => format == "pbf",
+        None => cli.output.ends_with(".pbf"),
+    }
+}
@@ -0,0 +1,25 @@
+//! Reporting of the conversion summary.
+
+use lantmateriet::summary::Summary;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, ErrorKind};
+
+/// Prints the summary to stderr and optionally writes it as json. In strict mode any kkod that
+/// is not in the config is an error.
+pub fn report(summary: &Summary, json: &Option<String>, strict: bool) -> io::Result<()> {
+    eprint!("{}", summary);
+
+    if let Some(path) = json {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, summary)?;
+    }
+
+    if strict && summary.has_unknown() {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "Found kkods that are not in the config (--strict)",
+        ));
+    }
+    Ok(())
+}
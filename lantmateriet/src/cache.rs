@@ -0,0 +1,194 @@
+//! Cache of converted layers, so a rerun only converts layers whose data or config changed.
+//!
+//! Each layer is stored as gzipped json in the cache folder together with a key, a hash of the
+//! shape file, the layer's section of the config, the title settings and the script. A layer is
+//! converted again if the key doesn't match, and the entry is replaced.
+
+use crate::config::{Config, FileConfig};
+use crate::process::Feature;
+use crate::shape::{Poly, Shape};
+use crate::summary::LayerSummary;
+use crate::vfs::Source;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
+use vadeen_osm::geo::Coordinate;
+
+/// Changed when the format of the entries or the conversion changes, invalidates old entries.
+const CACHE_VERSION: &str = "3";
+
+pub struct Cache {
+    folder: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    key: String,
+    summary: LayerSummary,
+    features: Vec<CachedFeature>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedFeature {
+    kkod: String,
+    index: usize,
+    attributes: HashMap<String, String>,
+    tags: Vec<(String, String)>,
+    kind: Kind,
+    /// Parts of (lat, lon), a point has one part with one coordinate.
+    parts: Vec<Vec<(f64, f64)>>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Kind {
+    Point,
+    Line,
+    Polygon,
+}
+
+impl Cache {
+    pub fn new<P: AsRef<Path>>(folder: P) -> Cache {
+        Cache {
+            folder: folder.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Key of a layer, from the content of its shape files, the layer config, the title settings
+    /// and the script.
+    pub(crate) fn key(
+        source: &Source,
+        base_name: &str,
+        config: &Config,
+        file_def: &FileConfig,
+    ) -> io::Result<String> {
+        let mut hasher = Sha256::new();
+        hasher.update(CACHE_VERSION);
+        for extension in &["shp", "dbf"] {
+            let mut data = Vec::new();
+            let name = format!("{}.{}", base_name, extension);
+            source.open_file(&name)?.read_to_end(&mut data)?;
+            hasher.update(data);
+        }
+
+        // Json maps are sorted, so the same config gives the same key.
+        let file_config = serde_json::to_value(file_def)?;
+        hasher.update(file_config.to_string());
+        let titles = serde_json::to_value((&config.title_tag, &config.language))?;
+        hasher.update(titles.to_string());
+
+        if let Some(script) = &config.script {
+            hasher.update(fs::read(script)?);
+        }
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    /// The features and summary of a layer, if the cache has them for `key`. Entries that can't
+    /// be read are treated as missing.
+    pub(crate) fn load(
+        &self,
+        name: &str,
+        key: &str,
+    ) -> Option<(LayerSummary, impl Iterator<Item = Feature>)> {
+        let file = File::open(self.path(name)).ok()?;
+        let entry: Entry = serde_json::from_reader(GzDecoder::new(BufReader::new(file))).ok()?;
+        if entry.key != key {
+            return None;
+        }
+
+        let layer = entry.summary.name.clone();
+        let features = entry
+            .features
+            .into_iter()
+            .map(move |f| f.into_feature(&layer));
+        Some((entry.summary, features))
+    }
+
+    pub(crate) fn store(
+        &self,
+        name: &str,
+        key: &str,
+        summary: &LayerSummary,
+        features: &[Feature],
+    ) -> io::Result<()> {
+        fs::create_dir_all(&self.folder)?;
+
+        let entry = Entry {
+            key: key.to_owned(),
+            summary: summary.clone(),
+            features: features.iter().map(CachedFeature::from).collect(),
+        };
+
+        let file = File::create(self.path(name))?;
+        let mut writer = GzEncoder::new(BufWriter::new(file), Compression::fast());
+        serde_json::to_writer(&mut writer, &entry)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.folder.join(format!("{}.json.gz", name.to_lowercase()))
+    }
+}
+
+impl From<&Feature> for CachedFeature {
+    fn from(feature: &Feature) -> Self {
+        let coordinates = |part: &Vec<Coordinate>| -> Vec<(f64, f64)> {
+            part.iter().map(|c| (c.lat(), c.lon())).collect()
+        };
+        let (kind, parts) = match &feature.shape {
+            Shape::Point(c) => (Kind::Point, vec![vec![(c.lat(), c.lon())]]),
+            Shape::PolyLine(poly) => (Kind::Line, poly.parts.iter().map(coordinates).collect()),
+            Shape::Polygon(poly) => (Kind::Polygon, poly.parts.iter().map(coordinates).collect()),
+        };
+
+        CachedFeature {
+            kkod: feature.kkod.clone(),
+            index: feature.index,
+            attributes: feature.attributes.clone(),
+            tags: feature
+                .tags
+                .iter()
+                .map(|t| (t.key.clone(), t.value.clone()))
+                .collect(),
+            kind,
+            parts,
+        }
+    }
+}
+
+impl CachedFeature {
+    fn into_feature(self, layer: &str) -> Feature {
+        let mut parts: Vec<Vec<Coordinate>> = self
+            .parts
+            .into_iter()
+            .map(|p| {
+                p.into_iter()
+                    .map(|(lat, lon)| Coordinate::new(lat, lon))
+                    .collect()
+            })
+            .collect();
+
+        let shape = match self.kind {
+            Kind::Point => Shape::Point(parts.remove(0).remove(0)),
+            Kind::Line => Shape::PolyLine(Poly::new(parts)),
+            Kind::Polygon => Shape::Polygon(Poly::new(parts)),
+        };
+
+        Feature {
+            layer: layer.to_owned(),
+            kkod: self.kkod,
+            index: self.index,
+            attributes: self.attributes,
+            tags: self.tags.into_iter().map(|t| t.into()).collect(),
+            shape,
+        }
+    }
+}
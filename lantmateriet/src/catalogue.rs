@@ -0,0 +1,122 @@
+//! Feature catalogue of a config, a legend of the tags and garmin type of every kkod per layer.
+
+use crate::config::{Config, FileConfig, GarminKind, Kkod};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CatalogueFormat {
+    Markdown,
+    Html,
+}
+
+/// Renders the catalogue. Titles are in `language` where the config has translations.
+pub fn catalogue(config: &Config, language: Option<&str>, format: CatalogueFormat) -> String {
+    let mut files: Vec<&FileConfig> = config.files.iter().collect();
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut out = String::new();
+    match format {
+        CatalogueFormat::Markdown => markdown(&mut out, &files, language),
+        CatalogueFormat::Html => html(&mut out, &files, language),
+    }
+    out
+}
+
+fn markdown(out: &mut String, files: &[&FileConfig], language: Option<&str>) {
+    out.push_str("# Feature catalogue\n");
+    for file in files {
+        writeln!(out, "\n## {} - {}\n", file.name, file.description).unwrap();
+        out.push_str("| Kkod | Title | Tags | Garmin |\n");
+        out.push_str("|---:|---|---|---|\n");
+        for (kkod, conf) in kkods(file) {
+            writeln!(
+                out,
+                "| {} | {} | {} | {} |",
+                kkod,
+                md_escape(conf.title(language).unwrap_or("")),
+                md_escape(&tags(conf)),
+                garmin(conf)
+            )
+            .unwrap();
+        }
+    }
+}
+
+fn html(out: &mut String, files: &[&FileConfig], language: Option<&str>) {
+    out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str("<title>Feature catalogue</title>\n</head>\n<body>\n");
+    out.push_str("<h1>Feature catalogue</h1>\n");
+    for file in files {
+        writeln!(
+            out,
+            "<h2>{} - {}</h2>",
+            html_escape(&file.name),
+            html_escape(&file.description)
+        )
+        .unwrap();
+        out.push_str("<table>\n<tr><th>Kkod</th><th>Title</th><th>Tags</th><th>Garmin</th></tr>\n");
+        for (kkod, conf) in kkods(file) {
+            writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                html_escape(kkod),
+                html_escape(conf.title(language).unwrap_or("")),
+                html_escape(&tags(conf)),
+                garmin(conf)
+            )
+            .unwrap();
+        }
+        out.push_str("</table>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+}
+
+/// Kkods sorted numerically.
+fn kkods(file: &FileConfig) -> Vec<(&String, &Kkod)> {
+    let mut kkods: Vec<(&String, &Kkod)> = file.kkods.iter().collect();
+    kkods.sort_by_key(|(k, _)| (k.parse::<u32>().unwrap_or(u32::MAX), k.to_string()));
+    kkods
+}
+
+/// Tags as `key=value`, sorted, and the number of conditional rules.
+fn tags(kkod: &Kkod) -> String {
+    let mut tags: Vec<String> = kkod
+        .tags
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect();
+    tags.sort();
+
+    let mut tags = tags.join(", ");
+    match kkod.rules.len() {
+        0 => (),
+        1 => tags.push_str(" (+1 rule)"),
+        n => write!(tags, " (+{} rules)", n).unwrap(),
+    }
+    tags
+}
+
+fn garmin(kkod: &Kkod) -> String {
+    match &kkod.garmin {
+        Some(g) => {
+            let kind = match g.kind {
+                GarminKind::Point => "point",
+                GarminKind::Line => "line",
+                GarminKind::Polygon => "polygon",
+            };
+            format!("{} {}, level {}", kind, g.garmin_type, g.level)
+        }
+        None => "-".to_owned(),
+    }
+}
+
+fn md_escape(s: &str) -> String {
+    s.replace('|', "\\|")
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
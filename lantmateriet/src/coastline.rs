@@ -0,0 +1,256 @@
+//! Coastlines of the sea in the land and water cover, `my`, for mkgmap's `--generate-sea`.
+//!
+//! `my` covers everything, so every segment of a water polygon is shared with a land polygon, with
+//! another water polygon or with nothing where the delivery is cut. The sea is the water polygons
+//! of at least `min_area` km² that reach the outer edge of the delivery, the sides of the rectangle
+//! around all of `my` in SWEREF 99 TM, other water is lakes. A lake cut by an inner edge of the
+//! delivery, e.g. a county border, is not sea. The segments of the sea shared with land are joined
+//! into `natural=coastline` ways. They keep the direction of the shape file, where the rings of a
+//! polygon go clockwise around the water, so the land is on the left as OSM expects.
+
+use crate::sweref99tm::from_wgs;
+use std::collections::{HashMap, HashSet};
+use vadeen_osm::geo::Coordinate;
+
+/// Layer and kkod the coastlines are converted as.
+pub const LAYER: &str = "coastline";
+pub const KKOD: &str = "coastline";
+
+/// Layer of the land and water polygons.
+pub const COVER_LAYER: &str = "my";
+
+pub const DEFAULT_MIN_AREA: f64 = 1.0;
+
+/// Square kilometers of a square degree at the equator.
+const SQUARE_DEGREE: f64 = 111.32 * 111.32;
+
+/// Meters a point may be from the outer edge of the delivery and still be on it.
+const EDGE_TOLERANCE: f64 = 1.0;
+
+/// Options of the coastline extraction, see `Lantmateriet::set_coastline`.
+#[derive(Debug, Clone)]
+pub struct Coastline {
+    /// Square kilometers a water polygon must cover to be sea.
+    pub min_area: f64,
+}
+
+/// Exact position of a point, as the bits of its latitude and longitude.
+type Point = (u64, u64);
+
+/// A segment, with the smaller point first.
+type Segment = (Point, Point);
+
+/// The water polygons that may be sea and the polygons next to them.
+pub struct Coastlines {
+    /// Record index and rings of the large water polygons.
+    water: Vec<(usize, Vec<Vec<Coordinate>>)>,
+    /// Record index of the polygons that have a segment, and whether they are land.
+    neighbours: HashMap<Segment, Vec<(usize, bool)>>,
+    /// South, west, north and east edge of the delivery in SWEREF 99 TM.
+    edges: [f64; 4],
+}
+
+impl Coastlines {
+    /// Takes every polygon of the cover as record index, rings and whether it is water. Water
+    /// polygons smaller than `min_area` km² are never sea.
+    pub fn new(polygons: Vec<(usize, Vec<Vec<Coordinate>>, bool)>, min_area: f64) -> Coastlines {
+        let mut edges = [f64::MAX, f64::MAX, f64::MIN, f64::MIN];
+        for c in polygons
+            .iter()
+            .flat_map(|(_, rings, _)| rings.iter().flatten())
+        {
+            let (north, east) = from_wgs(c);
+            edges = [
+                edges[0].min(north),
+                edges[1].min(east),
+                edges[2].max(north),
+                edges[3].max(east),
+            ];
+        }
+
+        let (water, others): (Vec<_>, Vec<_>) = polygons
+            .into_iter()
+            .partition(|(_, rings, water)| *water && area(rings) >= min_area);
+        let water: Vec<_> = water
+            .into_iter()
+            .map(|(index, rings, _)| (index, rings))
+            .collect();
+
+        // Only segments between points of the large water polygons can be coast.
+        let points: HashSet<Point> = water
+            .iter()
+            .flat_map(|(_, rings)| rings.iter().flatten())
+            .map(point)
+            .collect();
+        let mut neighbours: HashMap<Segment, Vec<(usize, bool)>> = HashMap::new();
+        let all = water
+            .iter()
+            .map(|(index, rings)| (*index, rings, false))
+            .chain(
+                others
+                    .iter()
+                    .map(|(index, rings, water)| (*index, rings, !water)),
+            );
+        for (index, rings, land) in all {
+            for pair in rings.iter().flat_map(|ring| ring.windows(2)) {
+                let (a, b) = (point(&pair[0]), point(&pair[1]));
+                if points.contains(&a) && points.contains(&b) {
+                    neighbours
+                        .entry(segment(a, b))
+                        .or_default()
+                        .push((index, land));
+                }
+            }
+        }
+
+        Coastlines {
+            water,
+            neighbours,
+            edges,
+        }
+    }
+
+    /// The coastlines with land on the left, closed around islands.
+    pub fn lines(&self) -> Vec<Vec<Coordinate>> {
+        let mut lines = Vec::new();
+        for (index, rings) in &self.water {
+            // Whether each segment of each ring is shared with land, and with anything at all.
+            let sides: Vec<Vec<(bool, bool)>> = rings
+                .iter()
+                .map(|ring| {
+                    ring.windows(2)
+                        .map(|pair| self.side(*index, &pair[0], &pair[1]))
+                        .collect()
+                })
+                .collect();
+
+            let at_edge = rings.iter().zip(&sides).any(|(ring, sides)| {
+                ring.windows(2)
+                    .zip(sides)
+                    .any(|(pair, &(_, shared))| !shared && self.on_edge(&pair[0], &pair[1]))
+            });
+            if !at_edge {
+                continue;
+            }
+            for (ring, sides) in rings.iter().zip(&sides) {
+                let coast: Vec<bool> = sides.iter().map(|&(land, _)| land).collect();
+                lines.extend(split(ring, &coast));
+            }
+        }
+        lines
+    }
+
+    /// Whether the segment of the water polygon at `index` is shared with land, and whether it is
+    /// shared with any other polygon.
+    fn side(&self, index: usize, a: &Coordinate, b: &Coordinate) -> (bool, bool) {
+        let others: Vec<bool> = self
+            .neighbours
+            .get(&segment(point(a), point(b)))
+            .map_or(&[][..], |n| n.as_slice())
+            .iter()
+            .filter(|(i, _)| *i != index)
+            .map(|&(_, land)| land)
+            .collect();
+        (others.iter().any(|&land| land), !others.is_empty())
+    }
+
+    /// Whether the segment lies along one of the outer edges of the delivery.
+    fn on_edge(&self, a: &Coordinate, b: &Coordinate) -> bool {
+        let (a, b) = (from_wgs(a), from_wgs(b));
+        let near = |x: f64, edge: f64| (x - edge).abs() <= EDGE_TOLERANCE;
+        (near(a.0, self.edges[0]) && near(b.0, self.edges[0]))
+            || (near(a.1, self.edges[1]) && near(b.1, self.edges[1]))
+            || (near(a.0, self.edges[2]) && near(b.0, self.edges[2]))
+            || (near(a.1, self.edges[3]) && near(b.1, self.edges[3]))
+    }
+}
+
+/// The runs of segments of a ring that are coast. A ring that is all coast stays closed.
+fn split(ring: &[Coordinate], coast: &[bool]) -> Vec<Vec<Coordinate>> {
+    let start = match coast.iter().position(|&c| !c) {
+        Some(i) => i + 1,
+        None if coast.is_empty() => return Vec::new(),
+        None => return vec![ring.to_vec()],
+    };
+
+    let n = coast.len();
+    let mut lines = Vec::new();
+    let mut line: Vec<Coordinate> = Vec::new();
+    for k in 0..n {
+        let i = (start + k) % n;
+        if coast[i] {
+            let from = if line.is_empty() { i } else { i + 1 };
+            line.extend_from_slice(&ring[from..=i + 1]);
+        } else if !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Approximate area in km² of a polygon, holes are subtracted by their opposite direction.
+fn area(rings: &[Vec<Coordinate>]) -> f64 {
+    let mut sum = 0.0;
+    let mut lat = 0.0;
+    for ring in rings {
+        for pair in ring.windows(2) {
+            sum += pair[0].lon() * pair[1].lat() - pair[1].lon() * pair[0].lat();
+        }
+        lat = ring.first().map_or(lat, |c| c.lat());
+    }
+    (sum / 2.0).abs() * SQUARE_DEGREE * lat.to_radians().cos()
+}
+
+fn point(c: &Coordinate) -> Point {
+    (c.lat().to_bits(), c.lon().to_bits())
+}
+
+/// The same segment in both directions.
+fn segment(a: Point, b: Point) -> Segment {
+    if a < b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sweref99tm::to_wgs;
+
+    /// A clockwise ring around a rectangle, kilometers from a point in Gävleborg.
+    fn rect(south: f64, west: f64, north: f64, east: f64) -> Vec<Vec<Coordinate>> {
+        let c = |n: f64, e: f64| to_wgs(6_700_000.0 + n * 1000.0, 500_000.0 + e * 1000.0);
+        vec![vec![
+            c(south, west),
+            c(north, west),
+            c(north, east),
+            c(south, east),
+            c(south, west),
+        ]]
+    }
+
+    /// Land around the water on three sides, the west side of the water is left open.
+    fn cover(water_west: f64) -> Vec<(usize, Vec<Vec<Coordinate>>, bool)> {
+        vec![
+            (0, rect(0.0, 0.0, 1.0, 4.0), false),
+            (1, rect(1.0, 2.0, 3.0, 4.0), false),
+            (2, rect(2.0, 0.0, 3.0, 2.0), false),
+            (3, rect(1.0, water_west, 2.0, 2.0), true),
+        ]
+    }
+
+    #[test]
+    fn water_at_the_outer_edge_is_sea() {
+        assert!(!Coastlines::new(cover(0.0), 0.5).lines().is_empty());
+    }
+
+    #[test]
+    fn water_at_an_inner_edge_is_a_lake() {
+        assert!(Coastlines::new(cover(1.0), 0.5).lines().is_empty());
+    }
+}
@@ -1,58 +1,164 @@
 //! Abstraction for the config specific to lantmäteriet's files.
 
+mod merge;
+pub mod rule;
+pub mod validate;
+
 use std::collections::HashMap;
 use std::fs::File;
 use std::io;
 
-use serde::Deserialize;
+use crate::config::rule::Rule;
+use serde::{Deserialize, Serialize};
 use std::io::Read;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use vadeen_osm::Tag;
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub files: Vec<FileConfig>,
+    pub draw_order: HashMap<String, u8>,
+    /// Script that is run for every record, see `script`.
+    pub script: Option<PathBuf>,
+    /// Tag to write the kkod title to, e.g. `lm:title`. No title tag if `None`.
+    pub title_tag: Option<String>,
+    /// Language of titles, e.g. `en`. Swedish if `None` or if the kkod has no translation.
+    pub language: Option<String>,
+    /// Look of the garmin types in the TYP file, see `typ`. The default types if `None`.
+    pub garmin_types: Option<PathBuf>,
+    /// Range of the osm ids, see `ids`. Ids in the order the elements are added if `None`.
+    pub ids: Option<IdRange>,
 }
 
-#[derive(Debug, Deserialize)]
+/// Osm ids from `start` up to but not including `end`.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct IdRange {
+    pub start: i64,
+    pub end: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct FileConfig {
     pub name: String,
     pub description: String,
     pub kkods: HashMap<String, Kkod>,
+    /// Zoom levels of the layer in vector tiles, see `tiles`. All zoom levels if `None`.
+    pub min_zoom: Option<u8>,
+    pub max_zoom: Option<u8>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Kkod {
+    pub title: Option<String>,
+    /// Titles in other languages by language code.
+    #[serde(default)]
+    pub translations: HashMap<String, String>,
     pub tags: HashMap<String, String>,
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    pub garmin: Option<Garmin>,
+}
+
+/// How a kkod is rendered on garmin devices.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Garmin {
+    pub kind: GarminKind,
+    #[serde(rename = "type")]
+    pub garmin_type: String,
+    pub level: u8,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum GarminKind {
+    Point,
+    Line,
+    Polygon,
 }
 
 #[derive(Debug, Deserialize)]
 struct ConfigFile {
     pub files: Vec<FileConfig>,
     pub render: RenderConfig,
+    #[serde(default)]
+    pub draw_order: HashMap<String, u8>,
+    pub script: Option<PathBuf>,
+    pub title_tag: Option<String>,
+    pub language: Option<String>,
+    pub garmin_types: Option<PathBuf>,
+    pub ids: Option<IdRange>,
 }
 
 type RenderConfig = HashMap<String, Option<Vec<String>>>;
 
+pub const DEFAULT_CONFIG: &str = include_str!("../resources/lantmateriet_default.yml");
+
 impl Config {
     pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Config> {
+        let config = Self::resolve_file(path)?;
+        Self::parse_resolved(&config)
+    }
+
+    /// Parses a config, `extends` paths are relative to the current directory.
+    pub fn parse_string(data: &str) -> io::Result<Config> {
+        let config = merge::resolve(data, Path::new("."), DEFAULT_CONFIG)?;
+        Self::parse_resolved(&config)
+    }
+
+    /// The effective config at `path` with everything it `extends` merged in, as yaml.
+    pub fn resolve_file<P: AsRef<Path>>(path: P) -> io::Result<String> {
+        let path = path.as_ref();
         let mut file = File::open(path)?;
         let mut config = String::new();
-
         file.read_to_string(&mut config)?;
-        Self::parse_string(&config)
+
+        let folder = path.parent().unwrap_or_else(|| Path::new("."));
+        merge::resolve(&config, folder, DEFAULT_CONFIG)
     }
 
-    pub fn parse_string(data: &str) -> io::Result<Config> {
-        let ConfigFile { render, files } = serde_yaml::from_str(data).unwrap();
+    fn parse_resolved(data: &str) -> io::Result<Config> {
+        let ConfigFile {
+            render,
+            files,
+            draw_order,
+            script,
+            title_tag,
+            language,
+            garmin_types,
+            ids,
+        } = serde_yaml::from_str(data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+        if let Some(range) = ids {
+            if range.start <= 0 || range.end <= range.start {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "ids must have a positive start and a greater end".to_owned(),
+                ));
+            }
+        }
 
-        let files: Vec<FileConfig> = files
+        let mut files: Vec<FileConfig> = files
             .into_iter()
             .filter(|f| render.contains_key(&f.name))
             .map(|c| Self::filter_kkods(c, &render))
             .collect();
 
-        Ok(Config { files })
+        for kkod in files.iter_mut().flat_map(|f| f.kkods.values_mut()) {
+            for rule in &mut kkod.rules {
+                rule.compile()?;
+            }
+        }
+
+        Ok(Config {
+            files,
+            draw_order,
+            script,
+            title_tag,
+            language,
+            garmin_types,
+            ids,
+        })
     }
 
     fn filter_kkods(file_config: FileConfig, render: &RenderConfig) -> FileConfig {
@@ -65,6 +171,8 @@ impl Config {
             kkods,
             description,
             name,
+            min_zoom,
+            max_zoom,
         } = file_config;
 
         let enabled_kkods = kkod_render_config.unwrap().as_ref().unwrap();
@@ -76,6 +184,8 @@ impl Config {
             kkods,
             name,
             description,
+            min_zoom,
+            max_zoom,
         }
     }
 }
@@ -87,4 +197,27 @@ impl Kkod {
             .map(|(k, v)| (k.as_ref(), v.as_ref()).into())
             .collect()
     }
+
+    /// The title in `language`, or the Swedish title if there is no translation.
+    pub fn title(&self, language: Option<&str>) -> Option<&str> {
+        language
+            .and_then(|l| self.translations.get(l))
+            .or(self.title.as_ref())
+            .map(|t| t.as_str())
+    }
+
+    /// The tags of a record, including tags of the rules that match its attributes.
+    pub fn tags_for(&self, attributes: &HashMap<String, String>) -> Vec<Tag> {
+        if self.rules.is_empty() {
+            return self.tags();
+        }
+
+        let mut tags = self.tags.clone();
+        for rule in self.rules.iter().filter(|r| r.matches(attributes)) {
+            tags.extend(rule.tags.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+        tags.iter()
+            .map(|(k, v)| (k.as_ref(), v.as_ref()).into())
+            .collect()
+    }
 }
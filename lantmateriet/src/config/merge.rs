@@ -0,0 +1,392 @@
+//! Layered configs, a config can `extends` the default config or other files and override parts
+//! of them.
+//!
+//! - `files` are merged by `name`, a file with `remove: true` removes it.
+//! - `kkods` are merged by kkod, a kkod with `remove: true` removes it.
+//! - `tags` and `translations` are merged by key, an empty value (`~`) removes it.
+//! - `render` and `draw_order` are merged by key. A render entry set to `false` and a draw order
+//!   set to `~` removes it.
+//! - Anything else, e.g. `title`, `description` and `garmin`, replaces the base value.
+//!
+//! The `script` and `garmin_types` paths are relative to the config they are in.
+
+use serde_yaml::{Mapping, Value};
+use std::fs;
+use std::io;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// Name in `extends` that refers to the embedded default config.
+const DEFAULT_NAME: &str = "default";
+
+/// Keys with paths relative to the config they are in.
+const PATH_KEYS: &[&str] = &["script", "garmin_types"];
+
+/// Max depth of `extends`, guards against cycles.
+const MAX_DEPTH: usize = 16;
+
+/// Merges all configs in `extends` of `data` and returns the resulting yaml. Paths in `extends`
+/// and `PATH_KEYS` are relative to `folder`. Returns `data` unchanged if it has none of them.
+pub fn resolve(data: &str, folder: &Path, default: &str) -> io::Result<String> {
+    let value = parse(data)?;
+    if key(&value, "extends").is_none() && PATH_KEYS.iter().all(|k| key(&value, k).is_none()) {
+        return Ok(data.to_owned());
+    }
+
+    let merged = resolve_value(value, folder, default, 0)?;
+    serde_yaml::to_string(&merged).map_err(invalid_data)
+}
+
+fn resolve_value(
+    mut value: Value,
+    folder: &Path,
+    default: &str,
+    depth: usize,
+) -> io::Result<Value> {
+    if depth > MAX_DEPTH {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "Config extends too deep, is there a cycle?",
+        ));
+    }
+
+    let extends = match value.as_mapping_mut() {
+        Some(mapping) => {
+            relative_paths(mapping, folder);
+            mapping.remove(&Value::from("extends"))
+        }
+        None => None,
+    };
+    let extends: Vec<String> = match extends {
+        None => Vec::new(),
+        Some(Value::String(name)) => vec![name],
+        Some(Value::Sequence(names)) => names
+            .iter()
+            .map(|n| n.as_str().map(|s| s.to_owned()))
+            .collect::<Option<_>>()
+            .ok_or_else(|| invalid_data("extends must be a name or a list of names"))?,
+        Some(_) => return Err(invalid_data("extends must be a name or a list of names")),
+    };
+
+    let mut base = Value::Mapping(Mapping::new());
+    for name in extends {
+        let parent = if name == DEFAULT_NAME {
+            parse(default)?
+        } else {
+            let path = folder.join(&name);
+            let data = fs::read_to_string(&path).map_err(|e| {
+                io::Error::new(e.kind(), format!("Could not read config {:?}: {}", path, e))
+            })?;
+            let parent_folder = path.parent().unwrap_or(folder);
+            resolve_value(parse(&data)?, parent_folder, default, depth + 1)?
+        };
+        base = merge_config(base, parent);
+    }
+
+    Ok(merge_config(base, value))
+}
+
+/// Makes relative paths in `PATH_KEYS` relative to the folder of the config instead.
+fn relative_paths(mapping: &mut Mapping, folder: &Path) {
+    for name in PATH_KEYS {
+        let name = Value::from(*name);
+        let path = match mapping.get(&name).and_then(Value::as_str) {
+            Some(path) => folder.join(path),
+            None => continue,
+        };
+        mapping.insert(name, Value::from(path.to_string_lossy().into_owned()));
+    }
+}
+
+fn merge_config(base: Value, over: Value) -> Value {
+    let (mut base, over) = match (base, over) {
+        (Value::Mapping(base), Value::Mapping(over)) => (base, over),
+        (_, over) => return over,
+    };
+
+    for (k, v) in over {
+        let old = base.remove(&k).unwrap_or(Value::Null);
+        let merged = match name(&k).as_str() {
+            "files" => merge_files(old, v),
+            "render" => merge_map(old, v, |v| *v == Value::Bool(false), replace),
+            "draw_order" => merge_map(old, v, Value::is_null, replace),
+            _ => v,
+        };
+        base.insert(k, merged);
+    }
+    Value::Mapping(base)
+}
+
+fn merge_files(base: Value, over: Value) -> Value {
+    let (mut base, over) = match (base, over) {
+        (Value::Sequence(base), Value::Sequence(over)) => (base, over),
+        (Value::Null, over) => (Vec::new(), over.as_sequence().cloned().unwrap_or_default()),
+        (_, over) => return over,
+    };
+
+    for file in over {
+        let file_name = key(&file, "name").map(name);
+        let index = base
+            .iter()
+            .position(|f| file_name.is_some() && key(f, "name").map(name) == file_name);
+
+        match index {
+            Some(i) if is_removed(&file) => {
+                base.remove(i);
+            }
+            Some(i) => {
+                let old = base.remove(i);
+                base.insert(i, merge_map(old, file, is_removed, merge_file_entry));
+            }
+            None if is_removed(&file) => (),
+            None => base.push(file),
+        }
+    }
+    Value::Sequence(base)
+}
+
+fn merge_file_entry(key: &str, old: Value, new: Value) -> Value {
+    match key {
+        "kkods" => merge_map(old, new, is_removed, merge_kkod),
+        _ => new,
+    }
+}
+
+fn merge_kkod(_: &str, old: Value, new: Value) -> Value {
+    merge_map(old, new, |_| false, merge_kkod_entry)
+}
+
+fn merge_kkod_entry(key: &str, old: Value, new: Value) -> Value {
+    match key {
+        "tags" | "translations" => merge_map(old, new, Value::is_null, replace),
+        _ => new,
+    }
+}
+
+fn replace(_: &str, _: Value, new: Value) -> Value {
+    new
+}
+
+/// Merges two maps key by key. Entries in `over` for which `removes` is true remove the entry
+/// from `base`, other entries that exist in both are merged with `merge`. Keys are compared by
+/// their string value, so `21` and `'21'` are the same kkod.
+fn merge_map(
+    base: Value,
+    over: Value,
+    removes: fn(&Value) -> bool,
+    merge: fn(&str, Value, Value) -> Value,
+) -> Value {
+    let (mut base, over) = match (base, over) {
+        (Value::Mapping(base), Value::Mapping(over)) => (base, over),
+        (Value::Null, Value::Mapping(over)) => (Mapping::new(), over),
+        (_, over) => return over,
+    };
+
+    for (k, v) in over {
+        let existing = base
+            .iter()
+            .map(|(b, _)| b)
+            .find(|b| name(b) == name(&k))
+            .cloned();
+        let old = existing.and_then(|e| base.remove(&e));
+
+        if removes(&v) {
+            continue;
+        }
+
+        let merged = match old {
+            Some(old) => merge(&name(&k), old, v),
+            None => v,
+        };
+        base.insert(k, merged);
+    }
+    Value::Mapping(base)
+}
+
+/// True for entries with `remove: true`.
+fn is_removed(value: &Value) -> bool {
+    key(value, "remove")
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+fn key<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    value.as_mapping()?.get(&Value::from(key))
+}
+
+/// String value of a scalar, used to compare keys.
+fn name(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        _ => String::new(),
+    }
+}
+
+fn parse(data: &str) -> io::Result<Value> {
+    serde_yaml::from_str(data).map_err(invalid_data)
+}
+
+fn invalid_data<E: ToString>(e: E) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const DEFAULT: &str = "
+render:
+  my:
+  vl:
+files:
+- name: my
+  kkods:
+    21:
+      title: Vattenyta
+      tags:
+        natural: water
+        water: lake
+    22:
+      title: Skog
+      tags:
+        natural: wood
+- name: vl
+  kkods:
+    5011:
+      title: Motorväg
+      tags:
+        highway: motorway
+";
+
+    /// An empty folder of its own for every test.
+    fn folder(name: &str) -> PathBuf {
+        let folder = std::env::temp_dir().join(format!(
+            "lantmateriet-merge-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    fn resolved(data: &str, folder: &Path) -> Value {
+        parse(&resolve(data, folder, DEFAULT).unwrap()).unwrap()
+    }
+
+    fn file<'a>(config: &'a Value, name: &str) -> Option<&'a Value> {
+        key(config, "files")?
+            .as_sequence()?
+            .iter()
+            .find(|f| key(f, "name").and_then(Value::as_str) == Some(name))
+    }
+
+    fn kkod<'a>(config: &'a Value, file_name: &str, code: u64) -> Option<&'a Value> {
+        key(file(config, file_name)?, "kkods")?
+            .as_mapping()?
+            .get(&Value::from(code))
+    }
+
+    #[test]
+    fn extends_the_default() {
+        let config = resolved("extends: default\nlanguage: en\n", Path::new("."));
+        assert!(kkod(&config, "vl", 5011).is_some());
+        assert_eq!(key(&config, "language"), Some(&Value::from("en")));
+        assert!(key(&config, "extends").is_none());
+    }
+
+    #[test]
+    fn later_configs_in_extends_override_earlier() {
+        let folder = folder("extends_list");
+        fs::write(folder.join("a.yml"), "title_tag: a\nlanguage: a\n").unwrap();
+        fs::write(folder.join("b.yml"), "extends: default\nlanguage: b\n").unwrap();
+
+        let config = resolved("extends: [a.yml, b.yml]\nid_tag: main\n", &folder);
+        assert_eq!(key(&config, "title_tag"), Some(&Value::from("a")));
+        assert_eq!(key(&config, "language"), Some(&Value::from("b")));
+        assert_eq!(key(&config, "id_tag"), Some(&Value::from("main")));
+        assert!(kkod(&config, "my", 21).is_some());
+    }
+
+    #[test]
+    fn remove_removes_files_and_kkods() {
+        let config = resolved(
+            "
+extends: default
+files:
+- name: vl
+  remove: true
+- name: my
+  kkods:
+    '22':
+      remove: true
+",
+            Path::new("."),
+        );
+        assert!(file(&config, "vl").is_none());
+        assert!(kkod(&config, "my", 21).is_some());
+        assert!(kkod(&config, "my", 22).is_none());
+    }
+
+    #[test]
+    fn tags_are_merged_by_key_and_null_removes() {
+        let config = resolved(
+            "
+extends: default
+files:
+- name: my
+  kkods:
+    21:
+      tags:
+        water: ~
+        name: Sjön
+",
+            Path::new("."),
+        );
+        let kkod = kkod(&config, "my", 21).unwrap();
+        assert_eq!(key(kkod, "title"), Some(&Value::from("Vattenyta")));
+
+        let tags = key(kkod, "tags").unwrap();
+        assert_eq!(key(tags, "natural"), Some(&Value::from("water")));
+        assert_eq!(key(tags, "name"), Some(&Value::from("Sjön")));
+        assert!(key(tags, "water").is_none());
+    }
+
+    #[test]
+    fn render_false_removes_the_layer() {
+        let config = resolved("extends: default\nrender:\n  vl: false\n", Path::new("."));
+        let render = key(&config, "render").unwrap();
+        assert!(key(render, "my").is_some());
+        assert!(key(render, "vl").is_none());
+    }
+
+    #[test]
+    fn paths_are_relative_to_their_config() {
+        let folder = folder("paths");
+        fs::create_dir_all(folder.join("base")).unwrap();
+        fs::write(folder.join("base/base.yml"), "script: transform.rhai\n").unwrap();
+
+        let config = resolved("extends: base/base.yml\ngarmin_types: types.yml\n", &folder);
+        let path = |name| {
+            key(&config, name)
+                .and_then(Value::as_str)
+                .map(PathBuf::from)
+        };
+        assert_eq!(path("script"), Some(folder.join("base/transform.rhai")));
+        assert_eq!(path("garmin_types"), Some(folder.join("types.yml")));
+    }
+
+    #[test]
+    fn cycles_are_an_error() {
+        let folder = folder("cycle");
+        fs::write(folder.join("a.yml"), "extends: b.yml\n").unwrap();
+        fs::write(folder.join("b.yml"), "extends: a.yml\n").unwrap();
+
+        let error = resolve("extends: a.yml\n", &folder, DEFAULT).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().contains("cycle"));
+    }
+}
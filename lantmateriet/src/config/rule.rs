@@ -0,0 +1,223 @@
+//! Conditional tags, picked per record from the values of its attributes.
+//!
+//! ```yaml
+//! rules:
+//! - if:
+//!     BRO: 1                  # Equal to a value.
+//!     VAGBR: { min: 7 }       # Within a range, min and max are inclusive.
+//!     NAMN: { regex: '^E\d' } # Matches a regex.
+//!     KLASS: { in: [1, 2] }   # One of several values.
+//!   tags:
+//!     bridge: 'yes'
+//!     layer: 1               # Osm's layer, the layer of the record is in `lm_layer`.
+//! ```
+//!
+//! All conditions of a rule must match. Tags of every matching rule are added in order, later
+//! rules override earlier ones and the kkod's own tags.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::io::ErrorKind;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Rule {
+    #[serde(rename = "if")]
+    pub conditions: HashMap<String, Condition>,
+    pub tags: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Condition {
+    Equals(Scalar),
+    Test(Test),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Test {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub regex: Option<String>,
+    #[serde(rename = "in")]
+    pub one_of: Option<Vec<Scalar>>,
+    #[serde(skip)]
+    compiled: Option<Regex>,
+}
+
+/// A plain yaml value, numbers are compared numerically and booleans also match the dBase
+/// logical values `T`, `F`, `Y` and `N`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Scalar {
+    Number(f64),
+    Bool(bool),
+    String(String),
+}
+
+impl Rule {
+    /// Compiles the regexes, must be called before `matches`. A test without any of its keys
+    /// would match every value and is an error.
+    pub(crate) fn compile(&mut self) -> io::Result<()> {
+        for (field, condition) in self.conditions.iter_mut() {
+            if let Condition::Test(test) = condition {
+                if test.min.is_none()
+                    && test.max.is_none()
+                    && test.regex.is_none()
+                    && test.one_of.is_none()
+                {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Condition on {} needs one of min, max, regex or in", field),
+                    ));
+                }
+                if let Some(regex) = &test.regex {
+                    let compiled = Regex::new(regex).map_err(|e| {
+                        io::Error::new(ErrorKind::InvalidData, format!("Invalid regex: {}", e))
+                    })?;
+                    test.compiled = Some(compiled);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// True if all conditions match the attributes. A missing attribute never matches.
+    pub fn matches(&self, attributes: &HashMap<String, String>) -> bool {
+        self.conditions.iter().all(|(field, condition)| {
+            attributes
+                .get(field)
+                .is_some_and(|value| condition.matches(value))
+        })
+    }
+}
+
+impl Condition {
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            Condition::Equals(scalar) => scalar.matches(value),
+            Condition::Test(test) => test.matches(value),
+        }
+    }
+}
+
+impl Test {
+    fn matches(&self, value: &str) -> bool {
+        let number = value.trim().parse::<f64>().ok();
+        if let Some(min) = self.min {
+            if !number.is_some_and(|n| n >= min) {
+                return false;
+            }
+        }
+        if let Some(max) = self.max {
+            if !number.is_some_and(|n| n <= max) {
+                return false;
+            }
+        }
+        if let Some(regex) = &self.compiled {
+            if !regex.is_match(value) {
+                return false;
+            }
+        }
+        if let Some(one_of) = &self.one_of {
+            if !one_of.iter().any(|s| s.matches(value)) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl Scalar {
+    fn matches(&self, value: &str) -> bool {
+        let value = value.trim();
+        match self {
+            Scalar::Number(n) => value.parse::<f64>().is_ok_and(|v| v == *n),
+            Scalar::Bool(b) => logical(value) == Some(*b),
+            Scalar::String(s) => value == s,
+        }
+    }
+}
+
+fn logical(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "t" | "y" => Some(true),
+        "false" | "f" | "n" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(yaml: &str) -> io::Result<Rule> {
+        let mut rule: Rule = serde_yaml::from_str(yaml)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e.to_string()))?;
+        rule.compile()?;
+        Ok(rule)
+    }
+
+    fn record(attributes: &[(&str, &str)]) -> HashMap<String, String> {
+        attributes
+            .iter()
+            .map(|&(k, v)| (k.to_owned(), v.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn numbers_equal_padded_dbase_values() {
+        let rule = rule("{ if: { BRO: 1 }, tags: { bridge: 'yes' } }").unwrap();
+        assert!(rule.matches(&record(&[("BRO", "  1")])));
+        assert!(rule.matches(&record(&[("BRO", "1.0 ")])));
+        assert!(!rule.matches(&record(&[("BRO", "0")])));
+    }
+
+    #[test]
+    fn ranges_are_inclusive() {
+        let rule = rule("{ if: { VAGBR: { min: 7, max: 12 } }, tags: { lanes: 2 } }").unwrap();
+        assert!(rule.matches(&record(&[("VAGBR", "7")])));
+        assert!(rule.matches(&record(&[("VAGBR", " 12.0")])));
+        assert!(!rule.matches(&record(&[("VAGBR", "6.9")])));
+        assert!(!rule.matches(&record(&[("VAGBR", "13")])));
+        assert!(!rule.matches(&record(&[("VAGBR", "")])));
+    }
+
+    #[test]
+    fn regex_and_in() {
+        let rule = rule(
+            "{ if: { NAMN: { regex: '^E\\d+' }, KLASS: { in: [1, a] } }, tags: { ref: 'yes' } }",
+        )
+        .unwrap();
+        assert!(rule.matches(&record(&[("NAMN", "E4"), ("KLASS", "1")])));
+        assert!(rule.matches(&record(&[("NAMN", "E45"), ("KLASS", "a")])));
+        assert!(!rule.matches(&record(&[("NAMN", "Riksväg 83"), ("KLASS", "1")])));
+        assert!(!rule.matches(&record(&[("NAMN", "E4"), ("KLASS", "2")])));
+    }
+
+    #[test]
+    fn booleans_match_dbase_logicals() {
+        let rule = rule("{ if: { BRO: true }, tags: { bridge: 'yes' } }").unwrap();
+        for value in &["T", "t", "Y", "y", "true"] {
+            assert!(rule.matches(&record(&[("BRO", value)])), "{}", value);
+        }
+        for value in &["F", "N", "false", "?", ""] {
+            assert!(!rule.matches(&record(&[("BRO", value)])), "{}", value);
+        }
+    }
+
+    #[test]
+    fn missing_attributes_never_match() {
+        let rule = rule("{ if: { NAMN: { regex: '.*' } }, tags: { name: 'yes' } }").unwrap();
+        assert!(!rule.matches(&record(&[("BRO", "1")])));
+    }
+
+    #[test]
+    fn typos_are_rejected() {
+        assert!(rule("{ if: { VAGBR: { mn: 7 } }, tags: { lanes: 2 } }").is_err());
+        assert!(rule("{ if: { VAGBR: {} }, tags: { lanes: 2 } }").is_err());
+        assert!(rule("{ if: { NAMN: { regex: '(' } }, tags: { lanes: 2 } }").is_err());
+    }
+}
@@ -0,0 +1,772 @@
+//! Validation of the config file against what the converter supports.
+//!
+//! serde throws away positions and silently merges duplicate keys, so the yaml is walked as a
+//! tree of marked nodes instead.
+
+use crate::tiles::MAX_ZOOM;
+use crate::validate::{Position, Problem};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use yaml_rust::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust::scanner::{Marker, TScalarStyle};
+
+const CONFIG_KEYS: &[&str] = &[
+    "extends",
+    "render",
+    "files",
+    "draw_order",
+    "script",
+    "title_tag",
+    "language",
+    "garmin_types",
+    "ids",
+];
+const FILE_KEYS: &[&str] = &[
+    "name",
+    "description",
+    "kkods",
+    "min_zoom",
+    "max_zoom",
+    "remove",
+];
+const KKOD_KEYS: &[&str] = &["title", "translations", "tags", "rules", "garmin", "remove"];
+const RULE_KEYS: &[&str] = &["if", "tags"];
+const TEST_KEYS: &[&str] = &["min", "max", "regex", "in"];
+const GARMIN_KEYS: &[&str] = &["kind", "type", "level"];
+const GARMIN_KINDS: &[&str] = &["point", "line", "polygon"];
+const IDS_KEYS: &[&str] = &["start", "end"];
+
+/// Max length of keys and values in osm.
+const MAX_TAG_LENGTH: usize = 255;
+
+#[derive(Debug)]
+enum Node {
+    Null,
+    Scalar(String),
+    Seq(Vec<Marked>),
+    /// Entries in file order, duplicate keys are kept.
+    Map(Vec<(Marked, Marked)>),
+}
+
+#[derive(Debug)]
+struct Marked {
+    node: Node,
+    position: Position,
+}
+
+/// Validates the config source. Problems are sorted by position.
+///
+/// A config that `extends` others only holds overrides, so required keys and references to files
+/// and kkods are not checked for it.
+pub fn validate(data: &str) -> Vec<Problem> {
+    let root = match parse(data) {
+        Ok(root) => root,
+        Err(problem) => return vec![problem],
+    };
+
+    let mut problems = Vec::new();
+    let entries = match &root.node {
+        Node::Map(entries) => entries,
+        _ => {
+            problems.push(Problem::error_at(
+                root.position,
+                "config must be a map".to_owned(),
+            ));
+            return problems;
+        }
+    };
+
+    check_keys(entries, CONFIG_KEYS, "config", &mut problems);
+    let partial = get(entries, "extends").is_some();
+
+    let mut kkods_by_file: HashMap<&str, HashSet<&str>> = HashMap::new();
+    match get(entries, "files") {
+        Some(Marked {
+            node: Node::Seq(files),
+            ..
+        }) => {
+            for file in files {
+                if let Some((name, kkods)) = validate_file(file, partial, &mut problems) {
+                    if kkods_by_file.insert(name, kkods).is_some() {
+                        problems.push(Problem::error_at(
+                            file.position,
+                            format!("duplicate file '{}'", name),
+                        ));
+                    }
+                }
+            }
+        }
+        Some(other) => problems.push(Problem::error_at(
+            other.position,
+            "files must be a list".to_owned(),
+        )),
+        None if partial => (),
+        None => problems.push(Problem::error_at(
+            root.position,
+            "missing key 'files'".to_owned(),
+        )),
+    }
+
+    match get(entries, "render") {
+        Some(Marked {
+            node: Node::Map(render),
+            ..
+        }) => validate_render(render, &kkods_by_file, partial, &mut problems),
+        Some(other) => problems.push(Problem::error_at(
+            other.position,
+            "render must be a map".to_owned(),
+        )),
+        None if partial => (),
+        None => problems.push(Problem::error_at(
+            root.position,
+            "missing key 'render'".to_owned(),
+        )),
+    }
+
+    check_scalar(entries, "script", "script must be a path", &mut problems);
+    check_scalar(
+        entries,
+        "title_tag",
+        "title_tag must be a tag key",
+        &mut problems,
+    );
+    check_scalar(
+        entries,
+        "language",
+        "language must be a language code",
+        &mut problems,
+    );
+    check_scalar(
+        entries,
+        "garmin_types",
+        "garmin_types must be a path",
+        &mut problems,
+    );
+    validate_ids(entries, &mut problems);
+
+    problems.sort_by_key(|p| p.position);
+    problems
+}
+
+/// Validates one entry in `files`, returns its name and kkods.
+fn validate_file<'a>(
+    file: &'a Marked,
+    partial: bool,
+    problems: &mut Vec<Problem>,
+) -> Option<(&'a str, HashSet<&'a str>)> {
+    let entries = match &file.node {
+        Node::Map(entries) => entries,
+        _ => {
+            problems.push(Problem::error_at(
+                file.position,
+                "file must be a map".to_owned(),
+            ));
+            return None;
+        }
+    };
+
+    check_keys(entries, FILE_KEYS, "file", problems);
+    for key in &["min_zoom", "max_zoom"] {
+        check_zoom(entries, key, problems);
+    }
+
+    let name = match get(entries, "name").map(|m| &m.node) {
+        Some(Node::Scalar(name)) => name.as_str(),
+        _ => {
+            problems.push(Problem::error_at(
+                file.position,
+                "file is missing 'name'".to_owned(),
+            ));
+            return None;
+        }
+    };
+
+    let mut kkods = HashSet::new();
+    match get(entries, "kkods") {
+        Some(Marked {
+            node: Node::Map(entries),
+            ..
+        }) => {
+            for (key, kkod) in entries {
+                let code = match &key.node {
+                    Node::Scalar(code) => code.as_str(),
+                    _ => continue,
+                };
+                if !kkods.insert(code) {
+                    problems.push(Problem::error_at(
+                        key.position,
+                        format!("duplicate kkod {} in file '{}'", code, name),
+                    ));
+                }
+                validate_kkod(kkod, partial, problems);
+            }
+        }
+        Some(other) => problems.push(Problem::error_at(
+            other.position,
+            format!("kkods of file '{}' must be a map", name),
+        )),
+        None if partial => (),
+        None => problems.push(Problem::error_at(
+            file.position,
+            format!("file '{}' is missing 'kkods'", name),
+        )),
+    }
+
+    Some((name, kkods))
+}
+
+/// Adds an error if `key` is set to something other than a scalar.
+fn check_scalar(
+    entries: &[(Marked, Marked)],
+    key: &str,
+    message: &str,
+    problems: &mut Vec<Problem>,
+) {
+    if let Some(value) = get(entries, key) {
+        if !matches!(value.node, Node::Scalar(_)) {
+            problems.push(Problem::error_at(value.position, message.to_owned()));
+        }
+    }
+}
+
+/// Adds an error unless `ids` is unset or a positive `start` and a greater `end`.
+fn validate_ids(entries: &[(Marked, Marked)], problems: &mut Vec<Problem>) {
+    let (ids, position) = match get(entries, "ids") {
+        None
+        | Some(Marked {
+            node: Node::Null, ..
+        }) => return,
+        Some(Marked {
+            node: Node::Map(ids),
+            position,
+        }) => (ids, *position),
+        Some(other) => {
+            problems.push(Problem::error_at(
+                other.position,
+                "ids must be a map with start and end".to_owned(),
+            ));
+            return;
+        }
+    };
+
+    check_keys(ids, IDS_KEYS, "ids", problems);
+    let id = |key: &str| match get(ids, key) {
+        Some(Marked {
+            node: Node::Scalar(id),
+            ..
+        }) => id.parse::<i64>().ok().filter(|&id| id > 0),
+        _ => None,
+    };
+    match (id("start"), id("end")) {
+        (Some(start), Some(end)) if start < end => (),
+        (Some(_), Some(_)) => problems.push(Problem::error_at(
+            position,
+            "ids end must be greater than start".to_owned(),
+        )),
+        _ => problems.push(Problem::error_at(
+            position,
+            "ids must have a positive start and end".to_owned(),
+        )),
+    }
+}
+
+/// Adds an error if `key` is set to something other than a zoom level.
+fn check_zoom(entries: &[(Marked, Marked)], key: &str, problems: &mut Vec<Problem>) {
+    if let Some(value) = get(entries, key) {
+        let valid = match &value.node {
+            Node::Scalar(zoom) => zoom.parse::<u8>().is_ok_and(|z| z <= MAX_ZOOM),
+            _ => false,
+        };
+        if !valid {
+            problems.push(Problem::error_at(
+                value.position,
+                format!("{} must be a zoom level from 0 to {}", key, MAX_ZOOM),
+            ));
+        }
+    }
+}
+
+fn validate_kkod(kkod: &Marked, partial: bool, problems: &mut Vec<Problem>) {
+    let entries = match &kkod.node {
+        Node::Map(entries) => entries,
+        _ => {
+            problems.push(Problem::error_at(
+                kkod.position,
+                "kkod must be a map".to_owned(),
+            ));
+            return;
+        }
+    };
+
+    check_keys(entries, KKOD_KEYS, "kkod", problems);
+
+    match get(entries, "tags") {
+        Some(Marked {
+            node: Node::Map(tags),
+            ..
+        }) => {
+            for (key, value) in tags {
+                validate_tag(key, value, partial, problems);
+            }
+        }
+        Some(other) => problems.push(Problem::error_at(
+            other.position,
+            "tags must be a map".to_owned(),
+        )),
+        None if partial => (),
+        None => problems.push(Problem::error_at(
+            kkod.position,
+            "kkod is missing 'tags'".to_owned(),
+        )),
+    }
+
+    match get(entries, "translations") {
+        Some(Marked {
+            node: Node::Map(translations),
+            ..
+        }) => {
+            for (_, title) in translations {
+                if !matches!(title.node, Node::Scalar(_) | Node::Null) {
+                    problems.push(Problem::error_at(
+                        title.position,
+                        "translation must be a title".to_owned(),
+                    ));
+                }
+            }
+        }
+        Some(other) => problems.push(Problem::error_at(
+            other.position,
+            "translations must be a map of language to title".to_owned(),
+        )),
+        None => (),
+    }
+
+    match get(entries, "rules") {
+        Some(Marked {
+            node: Node::Seq(rules),
+            ..
+        }) => {
+            for rule in rules {
+                validate_rule(rule, partial, problems);
+            }
+        }
+        Some(other) => problems.push(Problem::error_at(
+            other.position,
+            "rules must be a list".to_owned(),
+        )),
+        None => (),
+    }
+
+    if let Some(garmin) = get(entries, "garmin") {
+        validate_garmin(garmin, problems);
+    }
+}
+
+fn validate_rule(rule: &Marked, partial: bool, problems: &mut Vec<Problem>) {
+    let entries = match &rule.node {
+        Node::Map(entries) => entries,
+        _ => {
+            problems.push(Problem::error_at(
+                rule.position,
+                "rule must be a map".to_owned(),
+            ));
+            return;
+        }
+    };
+
+    check_keys(entries, RULE_KEYS, "rule", problems);
+
+    match get(entries, "if") {
+        Some(Marked {
+            node: Node::Map(conditions),
+            ..
+        }) => {
+            for (_, condition) in conditions {
+                validate_condition(condition, problems);
+            }
+        }
+        Some(other) => problems.push(Problem::error_at(
+            other.position,
+            "rule condition must be a map of attributes".to_owned(),
+        )),
+        None => problems.push(Problem::error_at(
+            rule.position,
+            "rule is missing 'if'".to_owned(),
+        )),
+    }
+
+    match get(entries, "tags") {
+        Some(Marked {
+            node: Node::Map(tags),
+            ..
+        }) => {
+            for (key, value) in tags {
+                validate_tag(key, value, partial, problems);
+            }
+        }
+        Some(other) => problems.push(Problem::error_at(
+            other.position,
+            "tags must be a map".to_owned(),
+        )),
+        None => problems.push(Problem::error_at(
+            rule.position,
+            "rule is missing 'tags'".to_owned(),
+        )),
+    }
+}
+
+fn validate_condition(condition: &Marked, problems: &mut Vec<Problem>) {
+    let entries = match &condition.node {
+        Node::Scalar(_) => return,
+        Node::Map(entries) => entries,
+        _ => {
+            problems.push(Problem::error_at(
+                condition.position,
+                "condition must be a value or a map".to_owned(),
+            ));
+            return;
+        }
+    };
+
+    // A test with an unknown key would match any value, so it's an error rather than a warning.
+    check_keys_as(entries, TEST_KEYS, "condition", Problem::error_at, problems);
+    if !TEST_KEYS.iter().any(|key| get(entries, key).is_some()) {
+        problems.push(Problem::error_at(
+            condition.position,
+            format!("condition needs one of {}", TEST_KEYS.join(", ")),
+        ));
+    }
+
+    for key in &["min", "max"] {
+        match get(entries, key) {
+            Some(Marked {
+                node: Node::Scalar(n),
+                ..
+            }) if n.parse::<f64>().is_ok() => (),
+            Some(other) => problems.push(Problem::error_at(
+                other.position,
+                format!("'{}' must be a number", key),
+            )),
+            None => (),
+        }
+    }
+
+    if let Some(regex) = get(entries, "regex") {
+        match &regex.node {
+            Node::Scalar(r) => {
+                if let Err(e) = Regex::new(r) {
+                    problems.push(Problem::error_at(regex.position, format!("{}", e)));
+                }
+            }
+            _ => problems.push(Problem::error_at(
+                regex.position,
+                "regex must be a string".to_owned(),
+            )),
+        }
+    }
+}
+
+fn validate_tag(key: &Marked, value: &Marked, partial: bool, problems: &mut Vec<Problem>) {
+    match &key.node {
+        Node::Scalar(key_str) if is_valid_key(key_str) => (),
+        Node::Scalar(key_str) => problems.push(Problem::error_at(
+            key.position,
+            format!("'{}' is not a valid osm key", key_str),
+        )),
+        _ => problems.push(Problem::error_at(
+            key.position,
+            "tag key must be a string".to_owned(),
+        )),
+    }
+
+    match &value.node {
+        Node::Scalar(v) if v.is_empty() => problems.push(Problem::error_at(
+            value.position,
+            "empty tag value".to_owned(),
+        )),
+        Node::Scalar(v) if v.chars().count() > MAX_TAG_LENGTH => problems.push(Problem::error_at(
+            value.position,
+            "tag value is too long".to_owned(),
+        )),
+        Node::Scalar(_) => (),
+        // Removes the tag from the extended config.
+        Node::Null if partial => (),
+        Node::Null => problems.push(Problem::error_at(
+            value.position,
+            "empty tag value".to_owned(),
+        )),
+        _ => problems.push(Problem::error_at(
+            value.position,
+            "tag value must be a string".to_owned(),
+        )),
+    }
+}
+
+fn validate_garmin(garmin: &Marked, problems: &mut Vec<Problem>) {
+    let entries = match &garmin.node {
+        Node::Map(entries) => entries,
+        _ => {
+            problems.push(Problem::error_at(
+                garmin.position,
+                "garmin must be a map".to_owned(),
+            ));
+            return;
+        }
+    };
+
+    check_keys(entries, GARMIN_KEYS, "garmin", problems);
+
+    match get(entries, "kind") {
+        Some(Marked {
+            node: Node::Scalar(kind),
+            ..
+        }) if GARMIN_KINDS.contains(&kind.as_str()) => (),
+        Some(other) => problems.push(Problem::error_at(
+            other.position,
+            format!("garmin kind must be one of {}", GARMIN_KINDS.join(", ")),
+        )),
+        None => problems.push(Problem::error_at(
+            garmin.position,
+            "garmin is missing 'kind'".to_owned(),
+        )),
+    }
+
+    match get(entries, "type") {
+        Some(Marked {
+            node: Node::Scalar(t),
+            ..
+        }) if t.starts_with("0x") && u32::from_str_radix(&t[2..], 16).is_ok() => (),
+        Some(other) => problems.push(Problem::error_at(
+            other.position,
+            "garmin type must be a hex string, e.g. '0x1a'".to_owned(),
+        )),
+        None => problems.push(Problem::error_at(
+            garmin.position,
+            "garmin is missing 'type'".to_owned(),
+        )),
+    }
+
+    match get(entries, "level") {
+        Some(Marked {
+            node: Node::Scalar(level),
+            ..
+        }) if level.parse::<u8>().is_ok() => (),
+        Some(other) => problems.push(Problem::error_at(
+            other.position,
+            "garmin level must be a number".to_owned(),
+        )),
+        None => problems.push(Problem::error_at(
+            garmin.position,
+            "garmin is missing 'level'".to_owned(),
+        )),
+    }
+}
+
+fn validate_render(
+    render: &[(Marked, Marked)],
+    kkods_by_file: &HashMap<&str, HashSet<&str>>,
+    partial: bool,
+    problems: &mut Vec<Problem>,
+) {
+    // Files and kkods may be defined in the configs that are extended.
+    if partial {
+        return;
+    }
+
+    for (key, value) in render {
+        let name = match &key.node {
+            Node::Scalar(name) => name.as_str(),
+            _ => continue,
+        };
+
+        let kkods = match kkods_by_file.get(name) {
+            Some(kkods) => kkods,
+            None => {
+                problems.push(Problem::error_at(
+                    key.position,
+                    format!("render references unknown file '{}'", name),
+                ));
+                continue;
+            }
+        };
+
+        match &value.node {
+            Node::Null => (),
+            Node::Seq(items) => {
+                for item in items {
+                    match &item.node {
+                        Node::Scalar(kkod) if kkods.contains(kkod.as_str()) => (),
+                        Node::Scalar(kkod) => problems.push(Problem::error_at(
+                            item.position,
+                            format!("render references unknown kkod {} in '{}'", kkod, name),
+                        )),
+                        _ => problems.push(Problem::error_at(
+                            item.position,
+                            "render kkod must be a number".to_owned(),
+                        )),
+                    }
+                }
+            }
+            _ => problems.push(Problem::error_at(
+                value.position,
+                "render value must be empty or a list of kkods".to_owned(),
+            )),
+        }
+    }
+}
+
+/// Reports keys that are not in `allowed` and keys that occur more than once.
+fn check_keys(
+    entries: &[(Marked, Marked)],
+    allowed: &[&str],
+    context: &str,
+    problems: &mut Vec<Problem>,
+) {
+    check_keys_as(entries, allowed, context, Problem::warning_at, problems);
+}
+
+/// Like `check_keys`, with keys that are not in `allowed` reported by `unsupported`.
+fn check_keys_as(
+    entries: &[(Marked, Marked)],
+    allowed: &[&str],
+    context: &str,
+    unsupported: fn(Position, String) -> Problem,
+    problems: &mut Vec<Problem>,
+) {
+    let mut seen = HashSet::new();
+    for (key, _) in entries {
+        let name = match &key.node {
+            Node::Scalar(name) => name.as_str(),
+            _ => continue,
+        };
+
+        if !seen.insert(name) {
+            problems.push(Problem::error_at(
+                key.position,
+                format!("duplicate key '{}' in {}", name, context),
+            ));
+        }
+
+        if !allowed.contains(&name) {
+            problems.push(unsupported(
+                key.position,
+                format!(
+                    "unsupported key '{}' in {}, expected one of {}",
+                    name,
+                    context,
+                    allowed.join(", ")
+                ),
+            ));
+        }
+    }
+}
+
+/// Osm keys are free form, but anything with whitespace or `=` is almost certainly a mistake.
+fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.chars().count() <= MAX_TAG_LENGTH
+        && !key
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || c == '=')
+}
+
+fn get<'a>(entries: &'a [(Marked, Marked)], key: &str) -> Option<&'a Marked> {
+    entries
+        .iter()
+        .find(|(k, _)| matches!(&k.node, Node::Scalar(name) if name == key))
+        .map(|(_, v)| v)
+}
+
+fn parse(data: &str) -> Result<Marked, Problem> {
+    let mut builder = TreeBuilder::default();
+    let mut parser = Parser::new(data.chars());
+    if let Err(e) = parser.load(&mut builder, false) {
+        let position = position(*e.marker());
+        return Err(Problem::error_at(position, e.to_string()));
+    }
+
+    builder.root.ok_or_else(|| {
+        Problem::error_at(
+            Position { line: 1, column: 1 },
+            "config is empty".to_owned(),
+        )
+    })
+}
+
+fn position(marker: Marker) -> Position {
+    Position {
+        line: marker.line(),
+        column: marker.col() + 1,
+    }
+}
+
+#[derive(Default)]
+struct TreeBuilder {
+    root: Option<Marked>,
+    stack: Vec<Marked>,
+    /// Pending key of each map in `stack`.
+    keys: Vec<Option<Marked>>,
+}
+
+impl TreeBuilder {
+    fn push(&mut self, node: Marked) {
+        let parent = match self.stack.last_mut() {
+            Some(parent) => parent,
+            None => {
+                self.root = Some(node);
+                return;
+            }
+        };
+
+        match &mut parent.node {
+            Node::Seq(items) => items.push(node),
+            Node::Map(entries) => {
+                let key = self.keys.last_mut().unwrap();
+                match key.take() {
+                    Some(k) => entries.push((k, node)),
+                    None => *key = Some(node),
+                }
+            }
+            Node::Null | Node::Scalar(_) => unreachable!("Scalars have no children"),
+        }
+    }
+}
+
+impl MarkedEventReceiver for TreeBuilder {
+    fn on_event(&mut self, event: Event, marker: Marker) {
+        let position = position(marker);
+        match event {
+            Event::Scalar(value, style, _, _) => {
+                let null = style == TScalarStyle::Plain
+                    && (value.is_empty() || value == "~" || value == "null");
+                let node = if null {
+                    Node::Null
+                } else {
+                    Node::Scalar(value)
+                };
+                self.push(Marked { node, position });
+            }
+            Event::SequenceStart(_) => {
+                self.stack.push(Marked {
+                    node: Node::Seq(Vec::new()),
+                    position,
+                });
+                self.keys.push(None);
+            }
+            Event::MappingStart(_) => {
+                self.stack.push(Marked {
+                    node: Node::Map(Vec::new()),
+                    position,
+                });
+                self.keys.push(None);
+            }
+            Event::SequenceEnd | Event::MappingEnd => {
+                self.keys.pop();
+                if let Some(node) = self.stack.pop() {
+                    self.push(node);
+                }
+            }
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,371 @@
+//! Contour lines traced from a height model, see `dem`.
+//!
+//! The contours replace the `oh` layer of the shape files. Every contour becomes a record of kkod
+//! 568 with the height in `HOJDVARDE`, so it gets the tags and style rules of the config's `oh`
+//! layer. Contours are traced with marching squares between the centers of the cells and smoothed
+//! by moving every point towards its neighbours. The squares between the last cells of a grid and
+//! the first cells of the grids to the east and south are traced too, and the lines of all grids
+//! are joined where they meet, so contours are unbroken at the seams.
+
+use crate::dem::{Grid, HeightModel};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+/// Layer and kkod the contours are converted as.
+pub const LAYER: &str = "oh";
+pub const KKOD: &str = "568";
+
+pub const DEFAULT_INTERVAL: f64 = 5.0;
+pub const DEFAULT_SMOOTHING: usize = 2;
+
+/// Contours shorter than this number of points are dropped, they are mostly noise in the grid.
+const MIN_POINTS: usize = 4;
+
+/// Height model files to generate contours from, see `Lantmateriet::set_contours`.
+#[derive(Debug, Clone)]
+pub struct Contours {
+    pub grids: Vec<PathBuf>,
+    /// Meters between contours.
+    pub interval: f64,
+    /// Number of smoothing passes.
+    pub smoothing: usize,
+}
+
+#[derive(Debug)]
+pub struct Contour {
+    pub height: f64,
+    /// North and east in sweref 99 TM. Closed contours end with their first point.
+    pub points: Vec<(f64, f64)>,
+}
+
+/// An edge between the centers of two cells, the cell at `col` and `row` and the cell to the east,
+/// or to the south if `south`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Edge {
+    col: usize,
+    row: usize,
+    south: bool,
+}
+
+impl Edge {
+    fn new(col: usize, row: usize, south: bool) -> Edge {
+        Edge { col, row, south }
+    }
+}
+
+/// Contours of the height model at every multiple of `interval`, lowest first.
+pub fn contours(model: &HeightModel, interval: f64, smoothing: usize) -> Vec<Contour> {
+    if interval <= 0.0 {
+        return Vec::new();
+    }
+
+    let mut levels: BTreeMap<i64, Vec<Vec<(f64, f64)>>> = BTreeMap::new();
+    for grid in model.grids() {
+        for (level, segments) in segments(model, grid, interval) {
+            let height = level as f64 * interval;
+            let lines = levels.entry(level).or_default();
+            for edges in join(&segments) {
+                lines.push(
+                    edges
+                        .iter()
+                        .map(|e| crossing(model, grid, e, height))
+                        .collect(),
+                );
+            }
+        }
+    }
+
+    let mut contours = Vec::new();
+    for (level, lines) in levels {
+        for points in stitch(lines) {
+            if points.len() < MIN_POINTS {
+                continue;
+            }
+            contours.push(Contour {
+                height: level as f64 * interval,
+                points: smooth(points, smoothing),
+            });
+        }
+    }
+    contours
+}
+
+/// The segments of the contours in every square of four cells of the grid, as the edges they cross,
+/// by level. The level of a contour is its height divided by `interval`. The squares along the
+/// east and south edges reach into the neighbouring grids.
+fn segments(model: &HeightModel, grid: &Grid, interval: f64) -> BTreeMap<i64, Vec<(Edge, Edge)>> {
+    let mut levels: BTreeMap<i64, Vec<(Edge, Edge)>> = BTreeMap::new();
+    for row in 0..grid.rows {
+        for col in 0..grid.cols {
+            // Corners clockwise from the north west.
+            let corners = [
+                (col, row),
+                (col + 1, row),
+                (col + 1, row + 1),
+                (col, row + 1),
+            ];
+            let mut heights = [0.0; 4];
+            let mut complete = true;
+            for (height, &(c, r)) in heights.iter_mut().zip(&corners) {
+                match height_of(model, grid, c, r) {
+                    Some(h) => *height = h,
+                    None => complete = false,
+                }
+            }
+            if !complete {
+                continue;
+            }
+
+            // The levels with corners on both sides, a corner at the level is above it.
+            let min = heights.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = heights.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            let first = (min / interval).floor() as i64 + 1;
+            let last = (max / interval).floor() as i64;
+            for level in first..=last {
+                let segments = levels.entry(level).or_default();
+                square(&heights, col, row, level as f64 * interval, segments);
+            }
+        }
+    }
+    levels
+}
+
+/// Adds the segments of the contour at `level` in the square with its north west corner at `col`
+/// and `row`, given the heights of its corners clockwise from the north west.
+fn square(
+    heights: &[f64; 4],
+    col: usize,
+    row: usize,
+    level: f64,
+    segments: &mut Vec<(Edge, Edge)>,
+) {
+    // The edges clockwise from the north.
+    let edges = [
+        Edge::new(col, row, false),
+        Edge::new(col + 1, row, true),
+        Edge::new(col, row + 1, false),
+        Edge::new(col, row, true),
+    ];
+    let above = heights.map(|h| h >= level);
+    let mut crossed = [edges[0]; 4];
+    let mut count = 0;
+    for i in 0..4 {
+        if above[i] != above[(i + 1) % 4] {
+            crossed[count] = edges[i];
+            count += 1;
+        }
+    }
+
+    match count {
+        2 => segments.push((crossed[0], crossed[1])),
+        4 => {
+            // A saddle. The corners on the other side of the level than the center are cut off by
+            // the two edges next to them.
+            let center = heights.iter().sum::<f64>() / 4.0 >= level;
+            for corner in (0..4).filter(|&i| above[i] != center) {
+                segments.push((edges[(corner + 3) % 4], edges[corner]));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The height of a cell. Cells past the east and south edges are taken from the neighbouring grids.
+fn height_of(model: &HeightModel, grid: &Grid, col: usize, row: usize) -> Option<f64> {
+    if col < grid.cols && row < grid.rows {
+        grid.height(col, row)
+    } else {
+        let (north, east) = grid.position(col as f64, row as f64);
+        model.cell_at(north, east)
+    }
+}
+
+/// Joins segments that share an edge into lines.
+fn join(segments: &[(Edge, Edge)]) -> Vec<Vec<Edge>> {
+    let mut by_edge: HashMap<Edge, Vec<usize>> = HashMap::new();
+    for (i, (a, b)) in segments.iter().enumerate() {
+        by_edge.entry(*a).or_default().push(i);
+        by_edge.entry(*b).or_default().push(i);
+    }
+
+    let mut used = vec![false; segments.len()];
+    let mut lines = Vec::new();
+    for start in 0..segments.len() {
+        if used[start] {
+            continue;
+        }
+        used[start] = true;
+        let (a, b) = segments[start];
+        let forward = follow(b, segments, &by_edge, &mut used);
+        let mut line = follow(a, segments, &by_edge, &mut used);
+        line.reverse();
+        line.push(a);
+        line.push(b);
+        line.extend(forward);
+        lines.push(line);
+    }
+    lines
+}
+
+/// The edges of the unused segments connected to `edge`, one after another.
+fn follow(
+    mut edge: Edge,
+    segments: &[(Edge, Edge)],
+    by_edge: &HashMap<Edge, Vec<usize>>,
+    used: &mut [bool],
+) -> Vec<Edge> {
+    let mut edges = Vec::new();
+    while let Some(&i) = by_edge[&edge].iter().find(|&&i| !used[i]) {
+        used[i] = true;
+        let (a, b) = segments[i];
+        edge = if a == edge { b } else { a };
+        edges.push(edge);
+    }
+    edges
+}
+
+/// Where the contour at `level` crosses an edge, interpolated between the heights of its cells.
+fn crossing(model: &HeightModel, grid: &Grid, edge: &Edge, level: f64) -> (f64, f64) {
+    let (col, row) = if edge.south {
+        (edge.col, edge.row + 1)
+    } else {
+        (edge.col + 1, edge.row)
+    };
+    let from = height_of(model, grid, edge.col, edge.row).unwrap_or(level);
+    let to = height_of(model, grid, col, row).unwrap_or(level);
+    let t = if to == from {
+        0.5
+    } else {
+        (level - from) / (to - from)
+    };
+    let col = edge.col as f64 + t * (col - edge.col) as f64;
+    let row = edge.row as f64 + t * (row - edge.row) as f64;
+    grid.position(col, row)
+}
+
+/// Joins lines that end at the same point, the lines of neighbouring grids meet at the seams.
+/// Lines that come back to where they start are closed with their first point.
+fn stitch(lines: Vec<Vec<(f64, f64)>>) -> Vec<Vec<(f64, f64)>> {
+    let mut by_end: HashMap<Point, Vec<usize>> = HashMap::new();
+    for (i, line) in lines.iter().enumerate() {
+        if let (Some(&first), Some(&last)) = (line.first(), line.last()) {
+            if point(first) != point(last) {
+                by_end.entry(point(first)).or_default().push(i);
+                by_end.entry(point(last)).or_default().push(i);
+            }
+        }
+    }
+
+    let mut lines: Vec<Option<Vec<(f64, f64)>>> = lines.into_iter().map(Some).collect();
+    let mut stitched = Vec::new();
+    for start in 0..lines.len() {
+        let mut line = match lines[start].take() {
+            Some(line) if !line.is_empty() => line,
+            _ => continue,
+        };
+        let forward = continue_line(line[line.len() - 1], &mut lines, &by_end);
+        let mut backward = continue_line(line[0], &mut lines, &by_end);
+        backward.reverse();
+        backward.append(&mut line);
+        backward.extend(forward);
+
+        let (first, last) = (backward[0], backward[backward.len() - 1]);
+        if point(first) == point(last) {
+            *backward.last_mut().unwrap() = first;
+        }
+        stitched.push(backward);
+    }
+    stitched
+}
+
+/// The points of the unused lines that continue from `end`, one after another.
+fn continue_line(
+    mut end: (f64, f64),
+    lines: &mut [Option<Vec<(f64, f64)>>],
+    by_end: &HashMap<Point, Vec<usize>>,
+) -> Vec<(f64, f64)> {
+    let mut points = Vec::new();
+    while let Some(i) = by_end
+        .get(&point(end))
+        .and_then(|ids| ids.iter().copied().find(|&i| lines[i].is_some()))
+    {
+        let mut line = lines[i].take().unwrap();
+        if point(line[0]) != point(end) {
+            line.reverse();
+        }
+        points.extend_from_slice(&line[1..]);
+        end = line[line.len() - 1];
+    }
+    points
+}
+
+/// A position rounded to millimeters, to find the ends of lines traced in different grids.
+type Point = (i64, i64);
+
+fn point((north, east): (f64, f64)) -> Point {
+    (
+        (north * 1000.0).round() as i64,
+        (east * 1000.0).round() as i64,
+    )
+}
+
+/// Moves every point halfway towards the middle of its neighbours, `passes` times. The ends of open
+/// lines are kept in place.
+fn smooth(mut points: Vec<(f64, f64)>, passes: usize) -> Vec<(f64, f64)> {
+    let closed = points.len() > 2 && points.first() == points.last();
+    for _ in 0..passes {
+        let n = points.len();
+        let previous = points.clone();
+        let neighbours = |i: usize| {
+            if closed {
+                // The first and last point are the same, skip it when wrapping around.
+                let before = if i == 0 { n - 2 } else { i - 1 };
+                let after = if i == n - 1 { 1 } else { i + 1 };
+                Some((previous[before], previous[after]))
+            } else if i == 0 || i == n - 1 {
+                None
+            } else {
+                Some((previous[i - 1], previous[i + 1]))
+            }
+        };
+        for (i, point) in points.iter_mut().enumerate() {
+            if let Some((before, after)) = neighbours(i) {
+                let middle = ((before.0 + after.0) / 2.0, (before.1 + after.1) / 2.0);
+                *point = ((point.0 + middle.0) / 2.0, (point.1 + middle.1) / 2.0);
+            }
+        }
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two grids side by side, sloping down to the south.
+    fn model() -> HeightModel {
+        let heights: Vec<f32> = (0..4).flat_map(|row| vec![3.5 - row as f32; 4]).collect();
+        HeightModel::new(vec![
+            Grid::new(4, 0.0, 4.0, 1.0, heights.clone()),
+            Grid::new(4, 4.0, 4.0, 1.0, heights),
+        ])
+    }
+
+    #[test]
+    fn contours_are_unbroken_at_seams() {
+        let contours = contours(&model(), 1.0, 0);
+        let heights: Vec<f64> = contours.iter().map(|c| c.height).collect();
+        assert_eq!(heights, vec![1.0, 2.0, 3.0]);
+        for contour in &contours {
+            let mut easts: Vec<f64> = contour.points.iter().map(|p| p.1).collect();
+            easts.sort_by(f64::total_cmp);
+            assert_eq!(easts.len(), 8);
+            assert_eq!((easts[0], easts[7]), (0.5, 7.5));
+        }
+    }
+
+    #[test]
+    fn no_contours_without_interval() {
+        assert!(contours(&model(), 0.0, 0).is_empty());
+    }
+}
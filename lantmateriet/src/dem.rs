@@ -0,0 +1,397 @@
+//! Lantmäteriet's height model, GSD-Höjddata, as a grid of heights in sweref 99 TM.
+//!
+//! The grid is read from an ESRI ASCII grid (`.asc`) or a GeoTIFF (`.tif`), the formats the height
+//! model is delivered in. Heights are in meters and are sampled at the centers of the cells. The
+//! height model is delivered as many small grids, `HeightModel` looks up heights in all of them,
+//! also at the seams where the cells to interpolate between are in different grids.
+
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, ErrorKind};
+use std::path::{Path, PathBuf};
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::tags::Tag;
+use tiff::TiffError;
+
+#[derive(Debug)]
+pub struct Grid {
+    pub cols: usize,
+    pub rows: usize,
+    /// East of the west edge and north of the north edge, in meters.
+    pub west: f64,
+    pub north: f64,
+    /// Side of a cell in meters.
+    pub cell_size: f64,
+    /// Heights row by row from the north, `NaN` where there is no data.
+    heights: Vec<f32>,
+}
+
+/// Several grids as one height model.
+#[derive(Debug)]
+pub struct HeightModel {
+    grids: Vec<Grid>,
+    /// Grids that overlap each square of `INDEX_SIZE` meters.
+    index: HashMap<(i64, i64), Vec<usize>>,
+}
+
+/// Side of the squares of the index of a height model, in meters.
+const INDEX_SIZE: f64 = 1000.0;
+
+impl HeightModel {
+    pub fn open(paths: &[PathBuf]) -> io::Result<HeightModel> {
+        let mut grids = Vec::with_capacity(paths.len());
+        for path in paths {
+            let grid = Grid::open(path)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+            grids.push(grid);
+        }
+        Ok(Self::new(grids))
+    }
+
+    pub fn new(grids: Vec<Grid>) -> HeightModel {
+        let mut index: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+        for (i, grid) in grids.iter().enumerate() {
+            let (south, west, north, east) = grid.extent();
+            for n in square(south)..=square(north) {
+                for e in square(west)..=square(east) {
+                    index.entry((n, e)).or_default().push(i);
+                }
+            }
+        }
+        HeightModel { grids, index }
+    }
+
+    pub fn grids(&self) -> &[Grid] {
+        &self.grids
+    }
+
+    /// The height at a position in the first grid that has data there. At the seams, where the
+    /// four cells around the position are in different grids, it is interpolated between the cells
+    /// of the grids next to each other. Where one of them has no data the height of the cell at the
+    /// position is used.
+    pub fn height_at(&self, north: f64, east: f64) -> Option<f64> {
+        if let Some(height) = self
+            .grids_at(north, east)
+            .find_map(|g| g.height_at(north, east))
+        {
+            return Some(height);
+        }
+
+        let grid = self
+            .grids_at(north, east)
+            .find(|g| g.contains(north, east))?;
+        let col = (east - grid.west) / grid.cell_size - 0.5;
+        let row = (grid.north - north) / grid.cell_size - 0.5;
+        let (dx, dy) = (col - col.floor(), row - row.floor());
+        let at = |c: f64, r: f64, weight: f64| {
+            if weight == 0.0 {
+                return Some(0.0);
+            }
+            let (north, east) = grid.position(col.floor() + c, row.floor() + r);
+            self.cell_at(north, east).map(|h| h * weight)
+        };
+        let interpolated = || {
+            Some(
+                at(0.0, 0.0, (1.0 - dx) * (1.0 - dy))?
+                    + at(1.0, 0.0, dx * (1.0 - dy))?
+                    + at(0.0, 1.0, (1.0 - dx) * dy)?
+                    + at(1.0, 1.0, dx * dy)?,
+            )
+        };
+        interpolated().or_else(|| self.cell_at(north, east))
+    }
+
+    /// The height of the cell a position is in, in the first grid that has data there.
+    pub fn cell_at(&self, north: f64, east: f64) -> Option<f64> {
+        self.grids_at(north, east)
+            .find_map(|g| g.cell_at(north, east))
+    }
+
+    fn grids_at(&self, north: f64, east: f64) -> impl Iterator<Item = &Grid> {
+        self.index
+            .get(&(square(north), square(east)))
+            .into_iter()
+            .flatten()
+            .map(move |&i| &self.grids[i])
+    }
+}
+
+impl Grid {
+    /// A grid of heights row by row from the north, `NaN` where there is no data.
+    pub fn new(cols: usize, west: f64, north: f64, cell_size: f64, heights: Vec<f32>) -> Grid {
+        Grid {
+            cols,
+            rows: heights.len() / cols.max(1),
+            west,
+            north,
+            cell_size,
+            heights,
+        }
+    }
+
+    /// Reads a GeoTIFF if the extension is `.tif` or `.tiff`, an ASCII grid otherwise.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Grid> {
+        let path = path.as_ref();
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("tif") | Some("tiff") => Self::read_geotiff(path),
+            _ => Self::parse_ascii(&fs::read_to_string(path)?),
+        }
+    }
+
+    /// The height of a cell, `None` if outside the grid or there is no data.
+    pub fn height(&self, col: usize, row: usize) -> Option<f64> {
+        if col >= self.cols || row >= self.rows {
+            return None;
+        }
+        let height = self.heights[row * self.cols + col];
+        if height.is_nan() {
+            None
+        } else {
+            Some(height.into())
+        }
+    }
+
+    /// The height of the cell a position is in, `None` if outside the grid or there is no data.
+    pub fn cell_at(&self, north: f64, east: f64) -> Option<f64> {
+        if !self.contains(north, east) {
+            return None;
+        }
+        let col = ((east - self.west) / self.cell_size) as usize;
+        let row = ((self.north - north) / self.cell_size) as usize;
+        self.height(col, row)
+    }
+
+    /// Whether a position is within the outer edges of the grid, the west and north edges included.
+    pub fn contains(&self, north: f64, east: f64) -> bool {
+        let (south, west, top, right) = self.extent();
+        north > south && north <= top && east >= west && east < right
+    }
+
+    /// North and east of the center of a cell.
+    pub fn position(&self, col: f64, row: f64) -> (f64, f64) {
+        let north = self.north - (row + 0.5) * self.cell_size;
+        let east = self.west + (col + 0.5) * self.cell_size;
+        (north, east)
+    }
+
+    /// The height at a position, interpolated between the centers of the four cells around it.
+    pub fn height_at(&self, north: f64, east: f64) -> Option<f64> {
+        let col = (east - self.west) / self.cell_size - 0.5;
+        let row = (self.north - north) / self.cell_size - 0.5;
+        if col < 0.0 || row < 0.0 {
+            return None;
+        }
+
+        let (c, r) = (col.floor() as usize, row.floor() as usize);
+        let (dx, dy) = (col - col.floor(), row - row.floor());
+        let at = |c: usize, r: usize, weight: f64| {
+            if weight == 0.0 {
+                Some(0.0)
+            } else {
+                self.height(c, r).map(|h| h * weight)
+            }
+        };
+        Some(
+            at(c, r, (1.0 - dx) * (1.0 - dy))?
+                + at(c + 1, r, dx * (1.0 - dy))?
+                + at(c, r + 1, (1.0 - dx) * dy)?
+                + at(c + 1, r + 1, dx * dy)?,
+        )
+    }
+
+    /// South, west, north and east of the outer edges of the grid.
+    pub fn extent(&self) -> (f64, f64, f64, f64) {
+        let south = self.north - self.rows as f64 * self.cell_size;
+        let east = self.west + self.cols as f64 * self.cell_size;
+        (south, self.west, self.north, east)
+    }
+
+    /// South, west, north and east of the centers of the outer cells.
+    pub fn bounds(&self) -> (f64, f64, f64, f64) {
+        let (north, west) = self.position(0.0, 0.0);
+        let (south, east) = self.position(self.cols as f64 - 1.0, self.rows as f64 - 1.0);
+        (south, west, north, east)
+    }
+
+    fn parse_ascii(data: &str) -> io::Result<Grid> {
+        let mut cols = None;
+        let mut rows = None;
+        let mut x = None;
+        let mut y = None;
+        let mut center = false;
+        let mut cell_size = None;
+        let mut nodata = None;
+
+        let mut lines = data.lines().peekable();
+        while let Some(line) = lines.peek() {
+            let mut words = line.split_whitespace();
+            let key = match words.next() {
+                Some(key) if key.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                    key.to_lowercase()
+                }
+                _ => break,
+            };
+            let value = words.next().unwrap_or("");
+            match key.as_str() {
+                "ncols" => cols = Some(parse(value)?),
+                "nrows" => rows = Some(parse(value)?),
+                "xllcorner" => x = Some(parse(value)?),
+                "yllcorner" => y = Some(parse(value)?),
+                "xllcenter" => {
+                    x = Some(parse(value)?);
+                    center = true;
+                }
+                "yllcenter" => y = Some(parse(value)?),
+                "cellsize" => cell_size = Some(parse(value)?),
+                "nodata_value" => nodata = Some(parse::<f32>(value)?),
+                _ => return Err(invalid(format!("Unknown grid header {}", key))),
+            }
+            lines.next();
+        }
+
+        let (cols, rows, x, y, cell_size): (usize, usize, f64, f64, f64) =
+            match (cols, rows, x, y, cell_size) {
+                (Some(cols), Some(rows), Some(x), Some(y), Some(cell_size)) => {
+                    (cols, rows, x, y, cell_size)
+                }
+                _ => return Err(invalid("Incomplete grid header".to_owned())),
+            };
+
+        let mut heights = Vec::with_capacity(cols * rows);
+        for value in lines.flat_map(|l| l.split_whitespace()) {
+            let height: f32 = parse(value)?;
+            heights.push(if Some(height) == nodata {
+                f32::NAN
+            } else {
+                height
+            });
+        }
+        if heights.len() != cols * rows {
+            return Err(invalid(format!(
+                "Expected {} heights in grid but found {}",
+                cols * rows,
+                heights.len()
+            )));
+        }
+
+        let offset = if center { cell_size / 2.0 } else { 0.0 };
+        Ok(Grid {
+            cols,
+            rows,
+            west: x - offset,
+            north: y - offset + rows as f64 * cell_size,
+            cell_size,
+            heights,
+        })
+    }
+
+    fn read_geotiff(path: &Path) -> io::Result<Grid> {
+        let mut decoder = Decoder::new(BufReader::new(File::open(path)?)).map_err(tiff_error)?;
+        let (cols, rows) = decoder.dimensions().map_err(tiff_error)?;
+        let scale = decoder
+            .get_tag_f64_vec(Tag::ModelPixelScaleTag)
+            .map_err(tiff_error)?;
+        let tiepoint = decoder
+            .get_tag_f64_vec(Tag::ModelTiepointTag)
+            .map_err(tiff_error)?;
+        if scale.len() < 2 || tiepoint.len() < 6 || scale[0] != scale[1] {
+            return Err(invalid(
+                "Expected square cells and a tie point in GeoTIFF".to_owned(),
+            ));
+        }
+        let nodata = match decoder.find_tag(Tag::GdalNodata).map_err(tiff_error)? {
+            Some(value) => Some(parse::<f32>(
+                value
+                    .into_string()
+                    .map_err(tiff_error)?
+                    .trim_end_matches('\0'),
+            )?),
+            None => None,
+        };
+
+        let heights: Vec<f32> = match decoder.read_image().map_err(tiff_error)? {
+            DecodingResult::F32(heights) => heights,
+            DecodingResult::F64(heights) => heights.into_iter().map(|h| h as f32).collect(),
+            DecodingResult::I16(heights) => heights.into_iter().map(f32::from).collect(),
+            DecodingResult::U16(heights) => heights.into_iter().map(f32::from).collect(),
+            DecodingResult::I32(heights) => heights.into_iter().map(|h| h as f32).collect(),
+            _ => return Err(invalid("Unsupported sample format in GeoTIFF".to_owned())),
+        };
+        let heights = heights
+            .into_iter()
+            .map(|h| if Some(h) == nodata { f32::NAN } else { h })
+            .collect();
+
+        let cell_size = scale[0];
+        Ok(Grid {
+            cols: cols as usize,
+            rows: rows as usize,
+            west: tiepoint[3] - tiepoint[0] * cell_size,
+            north: tiepoint[4] + tiepoint[1] * cell_size,
+            cell_size,
+            heights,
+        })
+    }
+}
+
+fn square(meters: f64) -> i64 {
+    (meters / INDEX_SIZE).floor() as i64
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> io::Result<T> {
+    value
+        .parse()
+        .map_err(|_| invalid(format!("Invalid number {} in grid", value)))
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+fn tiff_error(e: TiffError) -> io::Error {
+    match e {
+        TiffError::IoError(e) => e,
+        e => invalid(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(west: f64, heights: Vec<f32>) -> Grid {
+        Grid {
+            cols: 2,
+            rows: 2,
+            west,
+            north: 20.0,
+            cell_size: 10.0,
+            heights,
+        }
+    }
+
+    #[test]
+    fn interpolates_across_seams() {
+        let model = HeightModel::new(vec![
+            grid(0.0, vec![0.0, 10.0, 0.0, 10.0]),
+            grid(20.0, vec![20.0, 30.0, 20.0, 30.0]),
+        ]);
+        assert_eq!(model.height_at(10.0, 10.0), Some(5.0));
+        assert_eq!(model.height_at(10.0, 20.0), Some(15.0));
+        assert_eq!(model.height_at(10.0, 22.5), Some(17.5));
+        assert_eq!(model.height_at(10.0, 45.0), None);
+    }
+
+    #[test]
+    fn uses_the_cell_next_to_missing_data() {
+        let model = HeightModel::new(vec![
+            grid(0.0, vec![0.0, 10.0, 0.0, 10.0]),
+            grid(20.0, vec![f32::NAN, 30.0, f32::NAN, 30.0]),
+        ]);
+        assert_eq!(model.height_at(10.0, 17.5), Some(10.0));
+        assert_eq!(model.height_at(10.0, 22.5), None);
+    }
+}
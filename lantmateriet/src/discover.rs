@@ -0,0 +1,82 @@
+//! Finds the shape files and the region of a lantmäteriet delivery, in a folder or zip archive.
+//!
+//! Shape files are named `<layer>_<region>.shp`, e.g. `bl_21.shp`. Names are matched without
+//! regard to case since some deliveries use upper case names.
+
+use crate::vfs::Source;
+use std::collections::BTreeSet;
+use std::io;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// A `<layer>_<region>.shp` file in the folder.
+#[derive(Debug, Clone)]
+pub struct LayerFile {
+    /// Layer code in lower case, e.g. `bl`.
+    pub layer: String,
+    pub region: String,
+    /// File name without extension, as it is in the folder.
+    pub base_name: String,
+}
+
+/// Finds all shape files in `source` named `<layer>_<region>`, sorted by layer and region.
+/// Files with other names or names that aren't UTF-8 are ignored.
+pub fn scan(source: &Source) -> io::Result<Vec<LayerFile>> {
+    let mut files = Vec::new();
+    for name in source.file_names()? {
+        let (stem, extension) = match name.rsplit_once('.') {
+            Some(split) => split,
+            None => continue,
+        };
+        if !extension.eq_ignore_ascii_case("shp") {
+            continue;
+        }
+
+        if let Some((layer, region)) = stem.rsplit_once('_') {
+            if !layer.is_empty() && !region.is_empty() {
+                files.push(LayerFile {
+                    layer: layer.to_lowercase(),
+                    region: region.to_owned(),
+                    base_name: stem.to_owned(),
+                });
+            }
+        }
+    }
+
+    files.sort_by(|a, b| (&a.layer, &a.region).cmp(&(&b.layer, &b.region)));
+    Ok(files)
+}
+
+/// Finds the region of the files of the `known` layers. Fails if there are none, or if there are
+/// several regions.
+pub fn find_region(folder: &Path, files: &[LayerFile], known: &[&str]) -> io::Result<String> {
+    let regions: BTreeSet<&str> = files
+        .iter()
+        .filter(|f| known.iter().any(|k| k.eq_ignore_ascii_case(&f.layer)))
+        .map(|f| f.region.as_str())
+        .collect();
+
+    match regions.len() {
+        1 => Ok(regions.into_iter().next().unwrap().to_owned()),
+        0 if files.is_empty() => Err(io::Error::new(
+            ErrorKind::NotFound,
+            format!("No shape files found in {:?}.", folder),
+        )),
+        0 => Err(io::Error::new(
+            ErrorKind::NotFound,
+            format!(
+                "No shape files of the layers in the config ({}) found in {:?}.",
+                known.join(", "),
+                folder
+            ),
+        )),
+        _ => Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "Found several regions in {:?}: {}. Specify which region to use.",
+                folder,
+                regions.into_iter().collect::<Vec<_>>().join(", ")
+            ),
+        )),
+    }
+}
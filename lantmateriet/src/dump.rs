@@ -0,0 +1,111 @@
+//! Dumps the raw records of shape files, for debugging the mapping.
+
+use crate::config::FileConfig;
+use crate::shape::{Record, Shape};
+use crate::Lantmateriet;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io;
+use std::io::{ErrorKind, Write};
+use vadeen_osm::geo::Coordinate;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// One line per record with the attributes, sorted by name.
+    Text,
+    /// One json object per line, including the full geometry.
+    Json,
+}
+
+/// Dumps every record of `layer`, or of all layers in the config if `None`.
+pub fn dump<W: Write>(
+    lantmateriet: &Lantmateriet,
+    layer: Option<&str>,
+    format: DumpFormat,
+    writer: &mut W,
+) -> io::Result<()> {
+    let files = lantmateriet
+        .config()
+        .files
+        .iter()
+        .filter(|f| layer.is_none() || layer == Some(f.name.as_str()));
+
+    for file_def in files {
+        let shape = match lantmateriet.open_shape_file(file_def) {
+            Ok(shape) => shape,
+            Err(ref e) if e.kind() == ErrorKind::NotFound && layer.is_none() => continue,
+            Err(e) => return Err(e),
+        };
+
+        for (index, record) in shape.enumerate() {
+            match format {
+                DumpFormat::Text => write_text(writer, file_def, index, &record)?,
+                DumpFormat::Json => write_json(writer, file_def, index, &record)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_text<W: Write>(
+    writer: &mut W,
+    file_def: &FileConfig,
+    index: usize,
+    record: &Record,
+) -> io::Result<()> {
+    let attributes: BTreeMap<_, _> = record.attributes.iter().collect();
+    let attributes: Vec<String> = attributes
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect();
+
+    let geometry = match &record.shape {
+        Shape::Point(c) => format!("Point({:.6},{:.6})", c.lat(), c.lon()),
+        Shape::PolyLine(poly) | Shape::Polygon(poly) => format!(
+            "{}({} parts, {} points)",
+            record.shape.type_name(),
+            poly.parts.len(),
+            poly.parts.iter().map(|p| p.len()).sum::<usize>()
+        ),
+    };
+
+    writeln!(
+        writer,
+        "{} #{} {} {}",
+        file_def.name,
+        index,
+        geometry,
+        attributes.join(" ")
+    )
+}
+
+fn write_json<W: Write>(
+    writer: &mut W,
+    file_def: &FileConfig,
+    index: usize,
+    record: &Record,
+) -> io::Result<()> {
+    let coordinates = match &record.shape {
+        Shape::Point(c) => coordinate(c),
+        Shape::PolyLine(poly) | Shape::Polygon(poly) => Value::Array(
+            poly.parts
+                .iter()
+                .map(|part| Value::Array(part.iter().map(coordinate).collect()))
+                .collect(),
+        ),
+    };
+
+    let value = json!({
+        "layer": file_def.name,
+        "index": index,
+        "type": record.shape.type_name(),
+        "attributes": record.attributes,
+        "coordinates": coordinates,
+    });
+    serde_json::to_writer(&mut *writer, &value)?;
+    writeln!(writer)
+}
+
+fn coordinate(c: &Coordinate) -> Value {
+    json!([c.lat(), c.lon()])
+}
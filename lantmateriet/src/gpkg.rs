@@ -0,0 +1,550 @@
+//! GeoPackage copy of a delivery, every layer of the region as a feature table.
+//! See: https://www.geopackage.org/spec130/
+//!
+//! All records are written, also those with kkods that are not in the config, with the
+//! attributes of the dbf file as typed columns. Geometries are in sweref 99 TM like the shape
+//! files, and each table has an R-tree spatial index.
+
+use crate::shape::{FieldDescriptor, Shape, ShapeFile};
+use crate::sweref99tm::from_wgs;
+use crate::Lantmateriet;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, Transaction};
+use std::fs;
+use std::io;
+use std::io::ErrorKind;
+use std::path::Path;
+use vadeen_osm::geo::Coordinate;
+
+/// Sweref 99 TM.
+const SRS_ID: i32 = 3006;
+
+/// "GPKG" and version 1.3.
+const APPLICATION_ID: i32 = 0x4750_4B47;
+const USER_VERSION: i32 = 10300;
+
+const SCHEMA: &str = "
+    CREATE TABLE gpkg_spatial_ref_sys (
+        srs_name TEXT NOT NULL,
+        srs_id INTEGER NOT NULL PRIMARY KEY,
+        organization TEXT NOT NULL,
+        organization_coordsys_id INTEGER NOT NULL,
+        definition TEXT NOT NULL,
+        description TEXT
+    );
+    CREATE TABLE gpkg_contents (
+        table_name TEXT NOT NULL PRIMARY KEY,
+        data_type TEXT NOT NULL,
+        identifier TEXT UNIQUE,
+        description TEXT DEFAULT '',
+        last_change DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+        min_x DOUBLE,
+        min_y DOUBLE,
+        max_x DOUBLE,
+        max_y DOUBLE,
+        srs_id INTEGER,
+        CONSTRAINT fk_gc_r_srs_id FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys(srs_id)
+    );
+    CREATE TABLE gpkg_geometry_columns (
+        table_name TEXT NOT NULL,
+        column_name TEXT NOT NULL,
+        geometry_type_name TEXT NOT NULL,
+        srs_id INTEGER NOT NULL,
+        z TINYINT NOT NULL,
+        m TINYINT NOT NULL,
+        CONSTRAINT pk_geom_cols PRIMARY KEY (table_name, column_name),
+        CONSTRAINT uk_gc_table_name UNIQUE (table_name),
+        CONSTRAINT fk_gc_tn FOREIGN KEY (table_name) REFERENCES gpkg_contents(table_name),
+        CONSTRAINT fk_gc_srs FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys (srs_id)
+    );
+    CREATE TABLE gpkg_extensions (
+        table_name TEXT,
+        column_name TEXT,
+        extension_name TEXT NOT NULL,
+        definition TEXT NOT NULL,
+        scope TEXT NOT NULL,
+        CONSTRAINT ge_tce UNIQUE (table_name, column_name, extension_name)
+    );
+";
+
+/// Name, id, organization id, definition and description of the spatial reference systems.
+/// The undefined systems -1 and 0 are required by the spec.
+const SPATIAL_REF_SYS: &[(&str, i32, &str, i32, &str, &str)] = &[
+    (
+        "Undefined cartesian SRS",
+        -1,
+        "NONE",
+        -1,
+        "undefined",
+        "undefined cartesian coordinate reference system",
+    ),
+    (
+        "Undefined geographic SRS",
+        0,
+        "NONE",
+        0,
+        "undefined",
+        "undefined geographic coordinate reference system",
+    ),
+    (
+        "WGS 84 geodetic",
+        4326,
+        "EPSG",
+        4326,
+        "GEOGCS[\"WGS 84\",DATUM[\"WGS_1984\",SPHEROID[\"WGS 84\",6378137,298.257223563,\
+         AUTHORITY[\"EPSG\",\"7030\"]],AUTHORITY[\"EPSG\",\"6326\"]],PRIMEM[\"Greenwich\",0,\
+         AUTHORITY[\"EPSG\",\"8901\"]],UNIT[\"degree\",0.0174532925199433,\
+         AUTHORITY[\"EPSG\",\"9122\"]],AUTHORITY[\"EPSG\",\"4326\"]]",
+        "longitude/latitude coordinates in decimal degrees on the WGS 84 spheroid",
+    ),
+    (
+        "SWEREF99 TM",
+        SRS_ID,
+        "EPSG",
+        SRS_ID,
+        "PROJCS[\"SWEREF99 TM\",GEOGCS[\"SWEREF99\",DATUM[\"SWEREF99\",SPHEROID[\"GRS 1980\",\
+         6378137,298.257222101,AUTHORITY[\"EPSG\",\"7019\"]],TOWGS84[0,0,0,0,0,0,0],\
+         AUTHORITY[\"EPSG\",\"6619\"]],PRIMEM[\"Greenwich\",0,AUTHORITY[\"EPSG\",\"8901\"]],\
+         UNIT[\"degree\",0.0174532925199433,AUTHORITY[\"EPSG\",\"9122\"]],\
+         AUTHORITY[\"EPSG\",\"4619\"]],PROJECTION[\"Transverse_Mercator\"],\
+         PARAMETER[\"latitude_of_origin\",0],PARAMETER[\"central_meridian\",15],\
+         PARAMETER[\"scale_factor\",0.9996],PARAMETER[\"false_easting\",500000],\
+         PARAMETER[\"false_northing\",0],UNIT[\"metre\",1,AUTHORITY[\"EPSG\",\"9001\"]],\
+         AUTHORITY[\"EPSG\",\"3006\"]]",
+        "Lantmäteriet's national projection, used by the shape files",
+    ),
+];
+
+/// Number of records written per table.
+#[derive(Debug)]
+pub struct TableCount {
+    pub name: String,
+    pub records: usize,
+}
+
+/// Min x, min y, max x and max y.
+type Envelope = [f64; 4];
+
+/// Writes all layers of the region into a new GeoPackage at `path`, an existing file is replaced.
+pub fn write<P: AsRef<Path>>(path: P, lantmateriet: &Lantmateriet) -> io::Result<Vec<TableCount>> {
+    let path = path.as_ref();
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+
+    let mut connection = Connection::open(path).map_err(sql_error)?;
+    connection
+        .execute_batch(&format!(
+            "PRAGMA application_id = {}; PRAGMA user_version = {};",
+            APPLICATION_ID, USER_VERSION
+        ))
+        .map_err(sql_error)?;
+    connection.execute_batch(SCHEMA).map_err(sql_error)?;
+
+    let transaction = connection.transaction().map_err(sql_error)?;
+    for (name, id, organization, organization_id, definition, description) in SPATIAL_REF_SYS {
+        transaction
+            .execute(
+                "INSERT INTO gpkg_spatial_ref_sys VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    name,
+                    id,
+                    organization,
+                    organization_id,
+                    definition,
+                    description
+                ],
+            )
+            .map_err(sql_error)?;
+    }
+
+    let mut counts = Vec::new();
+    for file in lantmateriet.layer_files() {
+        let shape = ShapeFile::open(lantmateriet.source(), &file.base_name)?;
+        let description = lantmateriet
+            .config()
+            .files
+            .iter()
+            .find(|f| f.name.eq_ignore_ascii_case(&file.layer))
+            .map_or("", |f| f.description.as_str());
+
+        let records = write_table(&transaction, &file.layer, description, shape)?;
+        counts.push(TableCount {
+            name: file.layer.clone(),
+            records,
+        });
+    }
+    transaction.commit().map_err(sql_error)?;
+    Ok(counts)
+}
+
+/// Writes one layer as a feature table with its spatial index.
+fn write_table(
+    transaction: &Transaction,
+    table: &str,
+    description: &str,
+    shape: ShapeFile,
+) -> io::Result<usize> {
+    let geometry_type = match shape.header.type_name() {
+        "Point" => "POINT",
+        "PolyLine" => "MULTILINESTRING",
+        "Polygon" => "MULTIPOLYGON",
+        other => {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Unsupported geometry {} in layer {}", other, table),
+            ))
+        }
+    };
+
+    let fields: Vec<(String, char, u8)> = shape
+        .fields()
+        .iter()
+        .map(|f| (f.field_name.clone(), f.field_type, f.field_decimal_count))
+        .collect();
+    let columns: Vec<String> = shape.fields().iter().map(column).collect();
+    transaction
+        .execute_batch(&format!(
+            "CREATE TABLE {} (fid INTEGER PRIMARY KEY AUTOINCREMENT, geom {}{}{});",
+            quote(table),
+            geometry_type,
+            if columns.is_empty() { "" } else { ", " },
+            columns.join(", ")
+        ))
+        .map_err(sql_error)?;
+
+    let names: Vec<String> = fields.iter().map(|(name, _, _)| quote(name)).collect();
+    let placeholders: Vec<String> = (0..=fields.len()).map(|i| format!("?{}", i + 1)).collect();
+    let mut insert = transaction
+        .prepare(&format!(
+            "INSERT INTO {} (geom{}{}) VALUES ({})",
+            quote(table),
+            if names.is_empty() { "" } else { ", " },
+            names.join(", "),
+            placeholders.join(", ")
+        ))
+        .map_err(sql_error)?;
+
+    transaction
+        .execute_batch(&format!(
+            "CREATE VIRTUAL TABLE {} USING rtree(id, minx, maxx, miny, maxy);",
+            rtree(table)
+        ))
+        .map_err(sql_error)?;
+    let mut index = transaction
+        .prepare(&format!(
+            "INSERT INTO {} VALUES (?1, ?2, ?3, ?4, ?5)",
+            rtree(table)
+        ))
+        .map_err(sql_error)?;
+
+    let mut extent: Option<Envelope> = None;
+    let mut count = 0;
+    for record in shape {
+        let (geometry, envelope) = geometry(&record.shape);
+        extent = Some(match extent {
+            Some(e) => [
+                e[0].min(envelope[0]),
+                e[1].min(envelope[1]),
+                e[2].max(envelope[2]),
+                e[3].max(envelope[3]),
+            ],
+            None => envelope,
+        });
+
+        let mut values = vec![Value::Blob(geometry)];
+        for (name, field_type, decimals) in &fields {
+            let raw = record.attributes.get(name).map_or("", |v| v.as_str());
+            values.push(value(raw, *field_type, *decimals));
+        }
+        insert
+            .execute(params_from_iter(values.iter()))
+            .map_err(sql_error)?;
+        let [min_x, min_y, max_x, max_y] = envelope;
+        index
+            .execute(params![
+                transaction.last_insert_rowid(),
+                min_x,
+                max_x,
+                min_y,
+                max_y
+            ])
+            .map_err(sql_error)?;
+        count += 1;
+    }
+
+    let [min_x, min_y, max_x, max_y] = extent.unwrap_or_default();
+    transaction
+        .execute(
+            "INSERT INTO gpkg_contents
+             (table_name, data_type, identifier, description, min_x, min_y, max_x, max_y, srs_id)
+             VALUES (?1, 'features', ?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![table, description, min_x, min_y, max_x, max_y, SRS_ID],
+        )
+        .map_err(sql_error)?;
+    transaction
+        .execute(
+            "INSERT INTO gpkg_geometry_columns VALUES (?1, 'geom', ?2, ?3, 0, 0)",
+            params![table, geometry_type, SRS_ID],
+        )
+        .map_err(sql_error)?;
+
+    index_triggers(transaction, table)?;
+    Ok(count)
+}
+
+/// Creates the triggers that keep the R-tree of a table up to date. They are created after the
+/// table is filled, since they need the ST_ functions that GeoPackage readers provide.
+fn index_triggers(transaction: &Transaction, table: &str) -> io::Result<()> {
+    let rtree = rtree(table);
+    let t = quote(table);
+    let values = "NEW.fid, ST_MinX(NEW.geom), ST_MaxX(NEW.geom), ST_MinY(NEW.geom), \
+                  ST_MaxY(NEW.geom)";
+    let trigger = |suffix: &str| quote(&format!("rtree_{}_geom_{}", table, suffix));
+
+    let sql = format!(
+        "CREATE TRIGGER {insert} AFTER INSERT ON {t}
+        WHEN (NEW.geom NOT NULL AND NOT ST_IsEmpty(NEW.geom))
+        BEGIN
+            INSERT OR REPLACE INTO {rtree} VALUES ({values});
+        END;
+        CREATE TRIGGER {update1} AFTER UPDATE OF geom ON {t}
+        WHEN OLD.fid = NEW.fid AND (NEW.geom NOTNULL AND NOT ST_IsEmpty(NEW.geom))
+        BEGIN
+            INSERT OR REPLACE INTO {rtree} VALUES ({values});
+        END;
+        CREATE TRIGGER {update2} AFTER UPDATE OF geom ON {t}
+        WHEN OLD.fid = NEW.fid AND (NEW.geom ISNULL OR ST_IsEmpty(NEW.geom))
+        BEGIN
+            DELETE FROM {rtree} WHERE id = OLD.fid;
+        END;
+        CREATE TRIGGER {update3} AFTER UPDATE ON {t}
+        WHEN OLD.fid != NEW.fid AND (NEW.geom NOTNULL AND NOT ST_IsEmpty(NEW.geom))
+        BEGIN
+            DELETE FROM {rtree} WHERE id = OLD.fid;
+            INSERT OR REPLACE INTO {rtree} VALUES ({values});
+        END;
+        CREATE TRIGGER {update4} AFTER UPDATE ON {t}
+        WHEN OLD.fid != NEW.fid AND (NEW.geom ISNULL OR ST_IsEmpty(NEW.geom))
+        BEGIN
+            DELETE FROM {rtree} WHERE id IN (OLD.fid, NEW.fid);
+        END;
+        CREATE TRIGGER {delete} AFTER DELETE ON {t}
+        WHEN OLD.geom NOT NULL
+        BEGIN
+            DELETE FROM {rtree} WHERE id = OLD.fid;
+        END;",
+        rtree = rtree,
+        t = t,
+        values = values,
+        insert = trigger("insert"),
+        update1 = trigger("update1"),
+        update2 = trigger("update2"),
+        update3 = trigger("update3"),
+        update4 = trigger("update4"),
+        delete = trigger("delete"),
+    );
+    transaction.execute_batch(&sql).map_err(sql_error)?;
+
+    transaction
+        .execute(
+            "INSERT INTO gpkg_extensions VALUES (?1, 'geom', 'gpkg_rtree_index',
+             'http://www.geopackage.org/spec130/#extension_rtree', 'write-only')",
+            params![table],
+        )
+        .map_err(sql_error)?;
+    Ok(())
+}
+
+/// GeoPackage binary of a shape in sweref 99 TM, and its envelope. Lines and polygons are always
+/// multi geometries, like the layer's geometry type.
+fn geometry(shape: &Shape) -> (Vec<u8>, Envelope) {
+    let mut wkb = Vec::new();
+    let mut envelope = [f64::MAX, f64::MAX, f64::MIN, f64::MIN];
+    let mut point = |out: &mut Vec<u8>, c: &Coordinate| {
+        let (north, east) = from_wgs(c);
+        envelope = [
+            envelope[0].min(east),
+            envelope[1].min(north),
+            envelope[2].max(east),
+            envelope[3].max(north),
+        ];
+        out.extend_from_slice(&east.to_le_bytes());
+        out.extend_from_slice(&north.to_le_bytes());
+    };
+    match shape {
+        Shape::Point(c) => {
+            wkb_header(&mut wkb, WKB_POINT);
+            point(&mut wkb, c);
+        }
+        Shape::PolyLine(poly) => {
+            wkb_header(&mut wkb, WKB_MULTILINESTRING);
+            wkb.extend_from_slice(&(poly.parts.len() as u32).to_le_bytes());
+            for part in &poly.parts {
+                wkb_header(&mut wkb, WKB_LINESTRING);
+                points(&mut wkb, part.as_slice(), &mut point);
+            }
+        }
+        Shape::Polygon(poly) => {
+            let polygons = polygons(&poly.parts);
+            wkb_header(&mut wkb, WKB_MULTIPOLYGON);
+            wkb.extend_from_slice(&(polygons.len() as u32).to_le_bytes());
+            for rings in polygons {
+                wkb_header(&mut wkb, WKB_POLYGON);
+                wkb.extend_from_slice(&(rings.len() as u32).to_le_bytes());
+                for ring in rings {
+                    points(&mut wkb, ring, &mut point);
+                }
+            }
+        }
+    }
+
+    // Magic, version, flags for little endian with an xy envelope, srs id and the envelope.
+    let mut blob = vec![b'G', b'P', 0, 0b0000_0011];
+    blob.extend_from_slice(&SRS_ID.to_le_bytes());
+    for value in &[envelope[0], envelope[2], envelope[1], envelope[3]] {
+        blob.extend_from_slice(&value.to_le_bytes());
+    }
+    blob.extend(wkb);
+    (blob, envelope)
+}
+
+const WKB_POINT: u32 = 1;
+const WKB_LINESTRING: u32 = 2;
+const WKB_POLYGON: u32 = 3;
+const WKB_MULTILINESTRING: u32 = 5;
+const WKB_MULTIPOLYGON: u32 = 6;
+
+/// A point count followed by the points.
+fn points(
+    out: &mut Vec<u8>,
+    part: &[Coordinate],
+    point: &mut dyn FnMut(&mut Vec<u8>, &Coordinate),
+) {
+    out.extend_from_slice(&(part.len() as u32).to_le_bytes());
+    for c in part {
+        point(out, c);
+    }
+}
+
+fn wkb_header(out: &mut Vec<u8>, geometry_type: u32) {
+    out.push(1);
+    out.extend_from_slice(&geometry_type.to_le_bytes());
+}
+
+/// Groups the rings of a shape into polygons. Shape files have clockwise outer rings followed by
+/// their counterclockwise holes.
+fn polygons(rings: &[Vec<Coordinate>]) -> Vec<Vec<&[Coordinate]>> {
+    let mut polygons: Vec<Vec<&[Coordinate]>> = Vec::new();
+    for ring in rings {
+        match polygons.last_mut() {
+            Some(polygon) if !is_clockwise(ring) => polygon.push(ring.as_slice()),
+            _ => polygons.push(vec![ring.as_slice()]),
+        }
+    }
+    polygons
+}
+
+fn is_clockwise(ring: &[Coordinate]) -> bool {
+    let mut sum = 0.0;
+    for (i, a) in ring.iter().enumerate() {
+        let b = &ring[(i + 1) % ring.len()];
+        sum += a.lon() * b.lat() - b.lon() * a.lat();
+    }
+    sum < 0.0
+}
+
+/// The column definition of a dbf field.
+fn column(field: &FieldDescriptor) -> String {
+    let sql_type = match (field.field_type, field.field_decimal_count) {
+        ('N', 0) => "INTEGER",
+        ('N', _) | ('F', _) => "REAL",
+        ('L', _) => "BOOLEAN",
+        ('D', _) => "DATE",
+        _ => "TEXT",
+    };
+    format!("{} {}", quote(&field.field_name), sql_type)
+}
+
+/// The value of a dbf field in the column type, `NULL` if empty or invalid.
+fn value(raw: &str, field_type: char, decimals: u8) -> Value {
+    if raw.is_empty() {
+        return Value::Null;
+    }
+    match (field_type, decimals) {
+        ('N', 0) => raw.parse().map_or(Value::Null, Value::Integer),
+        ('N', _) | ('F', _) => raw.parse().map_or(Value::Null, Value::Real),
+        ('L', _) => match raw {
+            "T" | "t" | "Y" | "y" => Value::Integer(1),
+            "F" | "f" | "N" | "n" => Value::Integer(0),
+            _ => Value::Null,
+        },
+        ('D', _) if raw.len() == 8 && raw.bytes().all(|b| b.is_ascii_digit()) => {
+            Value::Text(format!("{}-{}-{}", &raw[..4], &raw[4..6], &raw[6..]))
+        }
+        ('D', _) => Value::Null,
+        _ => Value::Text(raw.to_owned()),
+    }
+}
+
+fn rtree(table: &str) -> String {
+    quote(&format!("rtree_{}_geom", table))
+}
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+fn sql_error(e: rusqlite::Error) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sweref99tm::to_wgs;
+
+    fn f64_at(blob: &[u8], at: usize) -> f64 {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&blob[at..at + 8]);
+        f64::from_le_bytes(bytes)
+    }
+
+    #[test]
+    fn point_blob() {
+        let (blob, _) = geometry(&Shape::Point(to_wgs(6_730_000.0, 615_000.0)));
+
+        // Header, srs id and the envelope as min x, max x, min y and max y.
+        assert_eq!(&blob[..4], &[b'G', b'P', 0, 0b0000_0011]);
+        assert_eq!(&blob[4..8], &SRS_ID.to_le_bytes());
+        for (i, expected) in [615_000.0, 615_000.0, 6_730_000.0, 6_730_000.0]
+            .iter()
+            .enumerate()
+        {
+            assert!((f64_at(&blob, 8 + i * 8) - expected).abs() < 0.01);
+        }
+
+        // Little endian wkb point, east then north.
+        assert_eq!(&blob[40..45], &[1, 1, 0, 0, 0]);
+        assert!((f64_at(&blob, 45) - 615_000.0).abs() < 0.01);
+        assert!((f64_at(&blob, 53) - 6_730_000.0).abs() < 0.01);
+        assert_eq!(blob.len(), 61);
+    }
+
+    #[test]
+    fn holes_follow_their_outer_ring() {
+        let ring = |points: &[(f64, f64)]| -> Vec<Coordinate> {
+            points
+                .iter()
+                .map(|&(lat, lon)| Coordinate::new(lat, lon))
+                .collect()
+        };
+        let outer = ring(&[(1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.0, 0.0), (1.0, 0.0)]);
+        let hole = ring(&[(0.6, 0.4), (0.4, 0.4), (0.4, 0.6), (0.6, 0.6), (0.6, 0.4)]);
+        let island = ring(&[(3.0, 2.0), (3.0, 3.0), (2.0, 3.0), (2.0, 2.0), (3.0, 2.0)]);
+
+        let rings = vec![outer, hole, island];
+        let sizes: Vec<usize> = polygons(&rings).iter().map(Vec::len).collect();
+        assert_eq!(sizes, vec![2, 1]);
+    }
+}
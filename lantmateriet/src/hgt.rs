@@ -0,0 +1,94 @@
+//! SRTM height files of a height model, for mkgmap's `--dem` option.
+//!
+//! An HGT file covers one degree of latitude and longitude, e.g. `N61E016.hgt` from 61°N 16°E, as
+//! rows of big endian 16 bit heights from the north. The height model is reprojected by sampling it
+//! at every point of the WGS 84 raster. Points without data are written as voids.
+
+use crate::dem::HeightModel;
+use crate::sweref99tm::{from_wgs, to_wgs};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use vadeen_osm::geo::Coordinate;
+
+/// Samples per side of a file with 1 and 3 arc seconds between points.
+pub const SRTM1: usize = 3601;
+pub const SRTM3: usize = 1201;
+
+const VOID: i16 = -32768;
+
+/// Writes a file for every degree the height model covers to `folder`, with `samples` points per
+/// side, see `SRTM1` and `SRTM3`. Returns the written files.
+pub fn write<P: AsRef<Path>>(
+    folder: P,
+    model: &HeightModel,
+    samples: usize,
+) -> io::Result<Vec<PathBuf>> {
+    let folder = folder.as_ref();
+    fs::create_dir_all(folder)?;
+
+    let mut files = Vec::new();
+    for (lat, lon) in degrees(model) {
+        let mut data = Vec::with_capacity(samples * samples * 2);
+        let mut empty = true;
+        for row in 0..samples {
+            let point_lat = f64::from(lat) + 1.0 - row as f64 / (samples - 1) as f64;
+            for col in 0..samples {
+                let point_lon = f64::from(lon) + col as f64 / (samples - 1) as f64;
+                let (north, east) = from_wgs(&Coordinate::new(point_lat, point_lon));
+                let height = match model.height_at(north, east) {
+                    Some(height) => {
+                        empty = false;
+                        height.round().clamp(-32767.0, 32767.0) as i16
+                    }
+                    None => VOID,
+                };
+                data.extend_from_slice(&height.to_be_bytes());
+            }
+        }
+
+        if !empty {
+            let path = folder.join(file_name(lat, lon));
+            fs::write(&path, data)?;
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// The south west corner of every degree the grids of the height model overlap.
+fn degrees(model: &HeightModel) -> BTreeSet<(i32, i32)> {
+    let mut degrees = BTreeSet::new();
+    for grid in model.grids() {
+        let (south, west, north, east) = grid.extent();
+        let corners = [
+            to_wgs(south, west),
+            to_wgs(south, east),
+            to_wgs(north, west),
+            to_wgs(north, east),
+        ];
+        let (mut min, mut max) = ((f64::MAX, f64::MAX), (f64::MIN, f64::MIN));
+        for c in &corners {
+            min = (min.0.min(c.lat()), min.1.min(c.lon()));
+            max = (max.0.max(c.lat()), max.1.max(c.lon()));
+        }
+        for lat in min.0.floor() as i32..=max.0.floor() as i32 {
+            for lon in min.1.floor() as i32..=max.1.floor() as i32 {
+                degrees.insert((lat, lon));
+            }
+        }
+    }
+    degrees
+}
+
+/// E.g. `N61E016.hgt`.
+fn file_name(lat: i32, lon: i32) -> String {
+    format!(
+        "{}{:02}{}{:03}.hgt",
+        if lat < 0 { 'S' } else { 'N' },
+        lat.abs(),
+        if lon < 0 { 'W' } else { 'E' },
+        lon.abs()
+    )
+}
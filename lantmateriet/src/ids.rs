@@ -0,0 +1,299 @@
+//! Stable osm ids, so the same data gets the same ids in every run.
+//!
+//! `OsmBuilder` numbers the elements in the order they are added, so a new layer or a changed
+//! config renumbers everything after it. `StableIds` derives the ids from where the elements come
+//! from instead. Ways are identified by the region, layer, record index and part of their feature,
+//! multipolygon relations by the region, layer and record index, and points like ways. The nodes
+//! of ways are identified by their position, so ways that meet share nodes.
+//!
+//! The identifiers are hashed into the config's `ids` range, which should be far from the ids of
+//! real osm data. A hash that collides with an id already taken gets the next free id, so only
+//! colliding elements depend on the order they are added in.
+
+use crate::config::IdRange;
+use crate::process::{Feature, Sink};
+use crate::shape::Shape;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::io;
+use vadeen_osm::geo::Coordinate;
+use vadeen_osm::{Meta, Node, Osm, Relation, RelationMember, Tag, Way};
+
+/// Builds an `Osm` with stable ids from the features of a region.
+pub struct StableIds {
+    region: String,
+    range: IdRange,
+    /// Ids of the nodes of ways by the bits of their latitude and longitude.
+    positions: HashMap<(u64, u64), i64>,
+    node_ids: HashSet<i64>,
+    way_ids: HashSet<i64>,
+    relation_ids: HashSet<i64>,
+    osm: Osm,
+}
+
+impl StableIds {
+    pub fn new(region: &str, range: IdRange) -> StableIds {
+        StableIds {
+            region: region.to_lowercase(),
+            range,
+            positions: HashMap::new(),
+            node_ids: HashSet::new(),
+            way_ids: HashSet::new(),
+            relation_ids: HashSet::new(),
+            osm: Osm::default(),
+        }
+    }
+
+    pub fn build(self) -> Osm {
+        self.osm
+    }
+
+    fn add_node(&mut self, key: &str, coordinate: Coordinate, tags: Vec<Tag>) -> io::Result<()> {
+        let id = assign(&mut self.node_ids, self.range, key)?;
+        self.osm.add_node(Node {
+            id,
+            coordinate,
+            meta: meta(tags),
+        });
+        Ok(())
+    }
+
+    fn add_way(&mut self, key: &str, points: &[Coordinate], tags: Vec<Tag>) -> io::Result<i64> {
+        let mut refs = Vec::with_capacity(points.len());
+        for c in points {
+            refs.push(self.position(c)?);
+        }
+        let id = assign(&mut self.way_ids, self.range, key)?;
+        self.osm.add_way(Way {
+            id,
+            refs,
+            meta: meta(tags),
+        });
+        Ok(id)
+    }
+
+    /// The untagged node at a position, added if there is none yet.
+    fn position(&mut self, c: &Coordinate) -> io::Result<i64> {
+        let position = (c.lat().to_bits(), c.lon().to_bits());
+        if let Some(&id) = self.positions.get(&position) {
+            return Ok(id);
+        }
+        let key = format!("node/{}/{}", c.lat(), c.lon());
+        let id = assign(&mut self.node_ids, self.range, &key)?;
+        self.positions.insert(position, id);
+        self.osm.add_node(Node {
+            id,
+            coordinate: *c,
+            meta: Meta::default(),
+        });
+        Ok(id)
+    }
+}
+
+/// Polylines with several parts are added as one way per part, polygons with several rings as a
+/// multipolygon of untagged ways. Clockwise rings are outer, as in shape files.
+impl Sink for StableIds {
+    fn add(&mut self, feature: Feature) -> io::Result<()> {
+        let Feature {
+            layer,
+            index,
+            shape,
+            tags,
+            ..
+        } = feature;
+        let key = format!("{}/{}/{}", self.region, layer, index);
+        match shape {
+            Shape::Point(c) => {
+                self.add_node(&format!("point/{}", key), c, tags)?;
+            }
+            Shape::PolyLine(poly) => {
+                for (part, points) in poly.parts.iter().enumerate() {
+                    self.add_way(&format!("way/{}/{}", key, part), points, tags.clone())?;
+                }
+            }
+            Shape::Polygon(poly) if poly.parts.len() == 1 => {
+                self.add_way(&format!("way/{}/0", key), &poly.parts[0], tags)?;
+            }
+            Shape::Polygon(poly) => {
+                let mut members = Vec::with_capacity(poly.parts.len());
+                for (part, ring) in poly.parts.iter().enumerate() {
+                    let id = self.add_way(&format!("way/{}/{}", key, part), ring, Vec::new())?;
+                    let role = if is_clockwise(ring) { "outer" } else { "inner" };
+                    members.push(RelationMember::Way(id, role.to_owned()));
+                }
+
+                let id = assign(
+                    &mut self.relation_ids,
+                    self.range,
+                    &format!("relation/{}", key),
+                )?;
+                let mut relation_tags: Vec<Tag> = vec![("type", "multipolygon").into()];
+                relation_tags.extend(tags);
+                self.osm.add_relation(Relation {
+                    id,
+                    members,
+                    meta: meta(relation_tags),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The id `key` hashes to, or the next free id after it if that is taken.
+fn assign(taken: &mut HashSet<i64>, range: IdRange, key: &str) -> io::Result<i64> {
+    let size = (range.end - range.start) as u64;
+    if taken.len() as u64 >= size {
+        return Err(io::Error::other(format!(
+            "No free osm ids left from {} to {}",
+            range.start, range.end
+        )));
+    }
+
+    let hash = Sha256::digest(key.as_bytes());
+    let hash = u64::from_be_bytes(hash[..8].try_into().unwrap());
+    let mut id = range.start + (hash % size) as i64;
+    while !taken.insert(id) {
+        id = if id + 1 == range.end {
+            range.start
+        } else {
+            id + 1
+        };
+    }
+    Ok(id)
+}
+
+fn meta(tags: Vec<Tag>) -> Meta {
+    Meta {
+        tags,
+        ..Meta::default()
+    }
+}
+
+fn is_clockwise(ring: &[Coordinate]) -> bool {
+    let mut sum = 0.0;
+    for (i, a) in ring.iter().enumerate() {
+        let b = &ring[(i + 1) % ring.len()];
+        sum += a.lon() * b.lat() - b.lon() * a.lat();
+    }
+    sum < 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape::Poly;
+
+    const RANGE: IdRange = IdRange {
+        start: 1_000_000_000_000_000,
+        end: 2_000_000_000_000_000,
+    };
+
+    fn feature(layer: &str, index: usize, shape: Shape) -> Feature {
+        Feature {
+            layer: layer.to_owned(),
+            kkod: "1".to_owned(),
+            index,
+            attributes: HashMap::new(),
+            shape,
+            tags: vec![("natural", "water").into()],
+        }
+    }
+
+    fn ring(points: &[(f64, f64)]) -> Vec<Coordinate> {
+        points
+            .iter()
+            .map(|&(lat, lon)| Coordinate::new(lat, lon))
+            .collect()
+    }
+
+    /// A lake with an island, a multipolygon.
+    fn lake() -> Feature {
+        let outer = ring(&[
+            (61.0, 16.0),
+            (61.0, 17.0),
+            (60.0, 17.0),
+            (60.0, 16.0),
+            (61.0, 16.0),
+        ]);
+        let island = ring(&[(60.6, 16.4), (60.4, 16.4), (60.4, 16.6), (60.6, 16.4)]);
+        feature("my", 7, Shape::Polygon(Poly::new(vec![outer, island])))
+    }
+
+    #[test]
+    fn ids_do_not_depend_on_what_is_added_before() {
+        let mut alone = StableIds::new("21", RANGE);
+        alone.add(lake()).unwrap();
+        let alone = alone.build();
+
+        let mut after = StableIds::new("21", RANGE);
+        let road = ring(&[(60.0, 16.0), (60.5, 16.5), (61.5, 17.5)]);
+        after
+            .add(feature("vl", 3, Shape::PolyLine(Poly::new(vec![road]))))
+            .unwrap();
+        after
+            .add(feature("tx", 0, Shape::Point(Coordinate::new(60.5, 16.0))))
+            .unwrap();
+        after.add(lake()).unwrap();
+        let after = after.build();
+
+        assert_eq!(alone.relations.len(), 1);
+        let relation = &alone.relations[0];
+        let same = after
+            .relations
+            .iter()
+            .find(|r| r.id == relation.id)
+            .unwrap();
+        assert_eq!(same.members, relation.members);
+
+        for way in &alone.ways {
+            let same = after.ways.iter().find(|w| w.id == way.id).unwrap();
+            assert_eq!(same.refs, way.refs);
+        }
+        for node in &alone.nodes {
+            let same = after.nodes.iter().find(|n| n.id == node.id).unwrap();
+            assert_eq!(same.coordinate, node.coordinate);
+        }
+    }
+
+    #[test]
+    fn ids_stay_in_the_range() {
+        let range = IdRange {
+            start: 100,
+            end: 110,
+        };
+        let mut ids = StableIds::new("21", range);
+        for index in 0..10 {
+            let point = Shape::Point(Coordinate::new(60.0, 16.0));
+            ids.add(feature("tx", index, point)).unwrap();
+        }
+        let point = Shape::Point(Coordinate::new(60.0, 16.0));
+        assert!(ids.add(feature("tx", 10, point)).is_err());
+
+        let osm = ids.build();
+        assert_eq!(osm.nodes.len(), 10);
+        assert!(osm.nodes.iter().all(|n| n.id >= 100 && n.id < 110));
+    }
+
+    #[test]
+    fn collisions_take_the_next_free_id_and_wrap() {
+        let range = IdRange { start: 5, end: 9 };
+        let (key, hashed) = ["a", "b", "c", "d", "e"]
+            .iter()
+            .map(|key| (*key, assign(&mut HashSet::new(), range, key).unwrap()))
+            .find(|(_, id)| *id != range.start)
+            .unwrap();
+
+        let mut taken: HashSet<i64> = vec![hashed].into_iter().collect();
+        let next = if hashed + 1 == range.end {
+            range.start
+        } else {
+            hashed + 1
+        };
+        assert_eq!(assign(&mut taken, range, key).unwrap(), next);
+
+        let mut taken: HashSet<i64> = (hashed..range.end).collect();
+        assert_eq!(assign(&mut taken, range, key).unwrap(), range.start);
+    }
+}
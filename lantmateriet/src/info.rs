@@ -0,0 +1,106 @@
+//! Summary of what a lantmäteriet folder contains.
+
+use crate::shape::ShapeFile;
+use crate::Lantmateriet;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use vadeen_osm::geo::Boundary;
+
+#[derive(Debug)]
+pub struct Info {
+    pub region: String,
+    pub layers: Vec<LayerInfo>,
+}
+
+#[derive(Debug)]
+pub struct LayerInfo {
+    pub name: String,
+    /// Description from the config, `None` if the layer isn't converted by the config.
+    pub description: Option<String>,
+    pub geometry: &'static str,
+    pub record_count: u32,
+    pub bounds: Boundary,
+    pub kkods: Vec<KkodCount>,
+}
+
+#[derive(Debug)]
+pub struct KkodCount {
+    pub kkod: String,
+    pub count: usize,
+    /// True if the kkod is converted by the config.
+    pub mapped: bool,
+}
+
+/// Collects info about every shape file of the region found in the folder.
+pub fn info(lantmateriet: &Lantmateriet) -> io::Result<Info> {
+    let mut layers = Vec::new();
+    for file in lantmateriet.layer_files() {
+        let name = file.layer.clone();
+        let mut shape = ShapeFile::open(lantmateriet.source(), &file.base_name)?;
+        let file_def = lantmateriet
+            .config()
+            .files
+            .iter()
+            .find(|f| f.name.eq_ignore_ascii_case(&name));
+
+        let mut histogram: BTreeMap<String, usize> = BTreeMap::new();
+        for record in &mut shape {
+            let kkod = record.attributes.get("KKOD").cloned().unwrap_or_default();
+            *histogram.entry(kkod).or_insert(0) += 1;
+        }
+
+        let mut kkods: Vec<KkodCount> = histogram
+            .into_iter()
+            .map(|(kkod, count)| KkodCount {
+                mapped: file_def.is_some_and(|f| f.kkods.contains_key(&kkod)),
+                kkod,
+                count,
+            })
+            .collect();
+        kkods.sort_by_key(|k| (k.kkod.parse::<u32>().unwrap_or(u32::MAX), k.kkod.clone()));
+
+        layers.push(LayerInfo {
+            name,
+            description: file_def.map(|f| f.description.clone()),
+            geometry: shape.header.type_name(),
+            record_count: shape.record_count(),
+            bounds: shape.header.bounds,
+            kkods,
+        });
+    }
+
+    Ok(Info {
+        region: lantmateriet.region().to_owned(),
+        layers,
+    })
+}
+
+impl fmt::Display for Info {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Region: {}", self.region)?;
+        for layer in &self.layers {
+            writeln!(f)?;
+            match &layer.description {
+                Some(description) => writeln!(f, "{} - {}", layer.name, description)?,
+                None => writeln!(f, "{} - not in config", layer.name)?,
+            }
+            writeln!(f, "  Geometry: {}", layer.geometry)?;
+            writeln!(f, "  Records:  {}", layer.record_count)?;
+            writeln!(
+                f,
+                "  Bounds:   {:.6},{:.6} to {:.6},{:.6}",
+                layer.bounds.min.lat(),
+                layer.bounds.min.lon(),
+                layer.bounds.max.lat(),
+                layer.bounds.max.lon()
+            )?;
+            writeln!(f, "  Kkods:")?;
+            for kkod in &layer.kkods {
+                let unmapped = if kkod.mapped { "" } else { "  (unmapped)" };
+                writeln!(f, "    {:>6} {:>8}{}", kkod.kkod, kkod.count, unmapped)?;
+            }
+        }
+        Ok(())
+    }
+}
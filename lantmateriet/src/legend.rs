@@ -0,0 +1,258 @@
+//! PNG legend of the garmin map, one row with a swatch and a label for each type in the TYP file.
+
+use crate::config::{Config, GarminKind};
+use crate::typ::{TypeStyle, Types};
+use font8x8::{UnicodeFonts, BASIC_FONTS, LATIN_FONTS};
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// File name of the legend written next to the TYP file.
+pub const LEGEND_FILE_NAME: &str = "legend.png";
+
+const WIDTH: usize = 640;
+const MARGIN: usize = 10;
+const ROW_HEIGHT: usize = 24;
+const HEADING_HEIGHT: usize = 32;
+const SWATCH_WIDTH: usize = 40;
+const SWATCH_HEIGHT: usize = 16;
+/// Text is the 8x8 font scaled by this.
+const TEXT_SCALE: usize = 2;
+
+const WHITE: [u8; 4] = [255, 255, 255, 255];
+const BLACK: [u8; 4] = [0, 0, 0, 255];
+const GREY: [u8; 4] = [128, 128, 128, 255];
+
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<[u8; 4]>,
+}
+
+/// Writes the legend of all types used by the config. Labels are in `language` if they exist.
+pub fn write_legend<P: AsRef<Path>>(
+    path: P,
+    config: &Config,
+    language: Option<&str>,
+) -> io::Result<()> {
+    let types = Types::for_config(config)?;
+    let canvas = legend(config, &types, language);
+
+    let file = File::create(path)?;
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(file),
+        canvas.width as u32,
+        canvas.height as u32,
+    );
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    let data: Vec<u8> = canvas.pixels.iter().flatten().copied().collect();
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&data))
+        .map_err(io::Error::other)
+}
+
+fn legend(config: &Config, types: &Types, language: Option<&str>) -> Canvas {
+    let used = types.used(config);
+    let sections = [
+        (GarminKind::Polygon, "Polygons"),
+        (GarminKind::Line, "Lines"),
+        (GarminKind::Point, "Points"),
+    ];
+
+    let mut rows = Vec::new();
+    for (kind, heading) in &sections {
+        let styled: Vec<_> = used
+            .iter()
+            .filter(|u| u.kind == *kind)
+            .filter_map(|u| {
+                u.style
+                    .map(|s| (s, u.label(language).unwrap_or(u.garmin_type)))
+            })
+            .collect();
+        if !styled.is_empty() {
+            rows.push((*heading, styled));
+        }
+    }
+
+    let height = MARGIN * 2
+        + rows
+            .iter()
+            .map(|(_, styled)| HEADING_HEIGHT + styled.len() * ROW_HEIGHT)
+            .sum::<usize>();
+    let mut canvas = Canvas::new(WIDTH, height.max(MARGIN * 2));
+
+    let mut y = MARGIN;
+    for (heading, styled) in rows {
+        canvas.text(MARGIN, y + 4, heading, BLACK);
+        y += HEADING_HEIGHT;
+        for (style, label) in styled {
+            swatch(
+                &mut canvas,
+                MARGIN,
+                y + (ROW_HEIGHT - SWATCH_HEIGHT) / 2,
+                style,
+            );
+            canvas.text(MARGIN * 2 + SWATCH_WIDTH, y + 4, label, BLACK);
+            y += ROW_HEIGHT;
+        }
+    }
+    canvas
+}
+
+/// Draws what the type looks like on the map in a `SWATCH_WIDTH` x `SWATCH_HEIGHT` box.
+fn swatch(canvas: &mut Canvas, x: usize, y: usize, style: &TypeStyle) {
+    let bitmap = bitmap(style);
+    let bitmap_height = bitmap.len();
+    let bitmap_width = bitmap.iter().map(Vec::len).max().unwrap_or(0);
+
+    match style.kind {
+        GarminKind::Polygon => {
+            if bitmap_width > 0 {
+                tile(canvas, x, y, SWATCH_WIDTH, SWATCH_HEIGHT, &bitmap);
+            } else {
+                canvas.fill(x, y, SWATCH_WIDTH, SWATCH_HEIGHT, color(&style.color));
+            }
+            canvas.outline(x, y, SWATCH_WIDTH, SWATCH_HEIGHT, GREY);
+        }
+        GarminKind::Line => {
+            if bitmap_width > 0 {
+                let top = y + SWATCH_HEIGHT.saturating_sub(bitmap_height) / 2;
+                tile(canvas, x, top, SWATCH_WIDTH, bitmap_height, &bitmap);
+            } else {
+                let width = style.line_width.unwrap_or(2) as usize;
+                let border = style.border_width.unwrap_or(0) as usize;
+                let total = (width + border * 2).min(SWATCH_HEIGHT);
+                let top = y + (SWATCH_HEIGHT - total) / 2;
+                canvas.fill(x, top, SWATCH_WIDTH, total, color(&style.border_color));
+                canvas.fill(x, top + border, SWATCH_WIDTH, width, color(&style.color));
+            }
+        }
+        GarminKind::Point => {
+            if bitmap_width > 0 {
+                let left = x + SWATCH_WIDTH.saturating_sub(bitmap_width) / 2;
+                let top = y + SWATCH_HEIGHT.saturating_sub(bitmap_height) / 2;
+                tile(canvas, left, top, bitmap_width, bitmap_height, &bitmap);
+            } else {
+                let left = x + (SWATCH_WIDTH - 6) / 2;
+                canvas.fill(left, y + (SWATCH_HEIGHT - 6) / 2, 6, 6, color(&style.color));
+            }
+        }
+    }
+}
+
+/// The bitmap of a style as colours, `None` is transparent.
+fn bitmap(style: &TypeStyle) -> Vec<Vec<Option<[u8; 4]>>> {
+    style
+        .bitmap
+        .iter()
+        .map(|row| {
+            row.chars()
+                .map(|c| {
+                    style
+                        .palette
+                        .iter()
+                        .find(|(symbol, _)| symbol.starts_with(c))
+                        .and_then(|(_, color)| parse_color(color))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Repeats the bitmap over the box.
+fn tile(
+    canvas: &mut Canvas,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    bitmap: &[Vec<Option<[u8; 4]>>],
+) {
+    for dy in 0..height {
+        let row = &bitmap[dy % bitmap.len()];
+        if row.is_empty() {
+            continue;
+        }
+        for dx in 0..width {
+            if let Some(color) = row[dx % row.len()] {
+                canvas.set(x + dx, y + dy, color);
+            }
+        }
+    }
+}
+
+/// Colour of `#RRGGBB`, or black if missing or invalid.
+fn color(color: &Option<String>) -> [u8; 4] {
+    color.as_deref().and_then(parse_color).unwrap_or(BLACK)
+}
+
+fn parse_color(color: &str) -> Option<[u8; 4]> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?, 255])
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Canvas {
+        Canvas {
+            width,
+            height,
+            pixels: vec![WHITE; width * height],
+        }
+    }
+
+    fn set(&mut self, x: usize, y: usize, color: [u8; 4]) {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x] = color;
+        }
+    }
+
+    fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, color: [u8; 4]) {
+        for dy in 0..height {
+            for dx in 0..width {
+                self.set(x + dx, y + dy, color);
+            }
+        }
+    }
+
+    fn outline(&mut self, x: usize, y: usize, width: usize, height: usize, color: [u8; 4]) {
+        self.fill(x, y, width, 1, color);
+        self.fill(x, y + height - 1, width, 1, color);
+        self.fill(x, y, 1, height, color);
+        self.fill(x + width - 1, y, 1, height, color);
+    }
+
+    /// Draws text until the right edge, characters missing in the font are left blank.
+    fn text(&mut self, x: usize, y: usize, text: &str, color: [u8; 4]) {
+        let advance = 8 * TEXT_SCALE;
+        for (i, c) in text.chars().enumerate() {
+            let left = x + i * advance;
+            if left + advance > self.width - MARGIN {
+                break;
+            }
+            let glyph = match BASIC_FONTS.get(c).or_else(|| LATIN_FONTS.get(c)) {
+                Some(glyph) => glyph,
+                None => continue,
+            };
+            for (row, bits) in glyph.iter().enumerate() {
+                for column in 0..8 {
+                    if bits & (1 << column) != 0 {
+                        self.fill(
+                            left + column * TEXT_SCALE,
+                            y + row * TEXT_SCALE,
+                            TEXT_SCALE,
+                            TEXT_SCALE,
+                            color,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,114 +1,606 @@
 extern crate vadeen_osm;
 
 mod binary;
-mod config;
+pub mod cache;
+pub mod catalogue;
+pub mod coastline;
+pub mod config;
+pub mod contour;
+pub mod dem;
+pub mod discover;
+pub mod dump;
+pub mod gpkg;
+pub mod hgt;
+pub mod ids;
+pub mod info;
+pub mod legend;
+pub mod pbf;
+pub mod peak;
+pub mod process;
+pub mod script;
 pub mod shape;
+pub mod split;
+pub mod style;
+pub mod summary;
 mod sweref99tm;
+pub mod tiles;
+pub mod typ;
+pub mod validate;
+pub mod vfs;
 
-use crate::config::{Config, FileConfig};
-use crate::shape::{Shape, ShapeFile};
+use crate::cache::Cache;
+use crate::coastline::{Coastline, Coastlines};
+use crate::config::{Config, FileConfig, DEFAULT_CONFIG};
+use crate::contour::Contours;
+use crate::dem::HeightModel;
+use crate::discover::LayerFile;
+use crate::ids::StableIds;
+use crate::peak::Peaks;
+use crate::process::{raw_tags, Feature, RawAttributes, RecordProcessor, Sink, LAYER_TAG};
+use crate::script::{Input, Script};
+use crate::shape::{Poly, Record, Shape, ShapeFile};
+use crate::summary::{LayerSummary, Summary};
+use crate::sweref99tm::{from_wgs, to_wgs};
+use crate::vfs::Source;
+use std::collections::HashMap;
 use std::fs;
 use std::io;
 use std::io::ErrorKind;
-use std::path::{Path, PathBuf};
-use vadeen_osm::{Osm, OsmBuilder, Tag};
+use std::path::Path;
+use vadeen_osm::{Osm, OsmBuilder};
 
 pub struct Lantmateriet {
-    folder: PathBuf,
+    source: Source,
     region: String,
+    /// Shape files of the region.
+    files: Vec<LayerFile>,
     config: Config,
+    script: Option<Script>,
+    cache: Option<Cache>,
+    contours: Option<Contours>,
+    peaks: Option<Peaks>,
+    coastline: Option<Coastline>,
+    raw_attributes: RawAttributes,
 }
 
-const DEFAULT_CONFIG: &str = include_str!("../resources/lantmateriet_default.yml");
-
 impl Lantmateriet {
     pub fn open<P: AsRef<Path>, C: AsRef<Path>>(
         folder: P,
         config: &Option<C>,
         region: &Option<String>,
     ) -> io::Result<Lantmateriet> {
-        let region = Self::find_region(&region, &folder)?;
+        let source = Source::open(folder)?;
         let config = Self::parse_config(config)?;
+        let script = script::open(&config.script)?;
+
+        let files = discover::scan(&source)?;
+        let region = match region {
+            Some(region) => region.clone(),
+            None => {
+                let known: Vec<&str> = config.files.iter().map(|f| f.name.as_str()).collect();
+                discover::find_region(source.path(), &files, &known)?
+            }
+        };
+        let files = files
+            .into_iter()
+            .filter(|f| f.region.eq_ignore_ascii_case(&region))
+            .collect();
+
         Ok(Lantmateriet {
-            folder: folder.as_ref().to_path_buf(),
+            source,
             region,
+            files,
             config,
+            script,
+            cache: None,
+            contours: None,
+            peaks: None,
+            coastline: None,
+            raw_attributes: RawAttributes::Off,
         })
     }
 
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The folder or zip archive.
+    pub fn folder(&self) -> &Path {
+        self.source.path()
+    }
+
+    pub fn source(&self) -> &Source {
+        &self.source
+    }
+
+    pub fn region(&self) -> &str {
+        &self.region
+    }
+
+    /// Caches converted layers in `folder`, see `cache`. Not used when converting with processors.
+    pub fn set_cache<P: AsRef<Path>>(&mut self, folder: P) {
+        self.cache = Some(Cache::new(folder));
+    }
+
+    /// Generates the contour layer from a height model instead of reading it from the shape files,
+    /// see `contour`. The contours are not cached.
+    pub fn set_contours(&mut self, contours: Contours) {
+        self.contours = Some(contours);
+    }
+
+    /// Converts the summits of a height model as the peak layer, see `peak`. The summits are not
+    /// cached.
+    pub fn set_peaks(&mut self, peaks: Peaks) {
+        self.peaks = Some(peaks);
+    }
+
+    /// Extracts the coastlines of the sea from the `my` layer as the coastline layer, see
+    /// `coastline`. The coastlines are not cached.
+    pub fn set_coastline(&mut self, coastline: Coastline) {
+        self.coastline = Some(coastline);
+    }
+
+    /// Adds the attributes of the records as `lm:` tags and converts records of unknown kkods, see
+    /// `RawAttributes`. The cache is not used unless it is `Off`.
+    pub fn set_raw_attributes(&mut self, raw_attributes: RawAttributes) {
+        self.raw_attributes = raw_attributes;
+    }
+
+    /// Shape files of the region in the folder, sorted by layer.
+    pub fn layer_files(&self) -> &[LayerFile] {
+        &self.files
+    }
+
+    /// Opens the shape file of a layer in the config.
+    pub fn open_shape_file(&self, file_def: &FileConfig) -> io::Result<ShapeFile> {
+        self.open_layer(&file_def.name)
+    }
+
+    /// Opens the shape file of a layer by name, e.g. `my`.
+    pub fn open_layer(&self, layer: &str) -> io::Result<ShapeFile> {
+        let file = self.layer_file(layer)?;
+        ShapeFile::open(&self.source, &file.base_name)
+    }
+
+    fn layer_file(&self, layer: &str) -> io::Result<&LayerFile> {
+        match self
+            .files
+            .iter()
+            .find(|f| f.layer.eq_ignore_ascii_case(layer))
+        {
+            Some(file) => Ok(file),
+            None => Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!(
+                    "No shape file for layer {} in region {} found.",
+                    layer, self.region
+                ),
+            )),
+        }
+    }
+
     pub fn read(self) -> io::Result<Osm> {
+        self.read_with_summary().map(|(osm, _)| osm)
+    }
+
+    /// Like `read` but also counts converted, skipped and unknown records per layer.
+    pub fn read_with_summary(self) -> io::Result<(Osm, Summary)> {
+        if let Some(range) = self.config.ids {
+            let mut osm = StableIds::new(&self.region, range);
+            let summary = self.convert(&mut [], &mut osm)?;
+            return Ok((osm.build(), summary));
+        }
+
         let mut osm = OsmBuilder::default();
+        let summary = self.convert(&mut [], &mut osm)?;
+        Ok((osm.build(), summary))
+    }
+
+    /// Converts every layer in the config, passes the features through `processors` in order and
+    /// adds the ones that are left to `sink`. See `process`.
+    pub fn convert<S: Sink>(
+        &self,
+        processors: &mut [&mut dyn RecordProcessor],
+        sink: &mut S,
+    ) -> io::Result<Summary> {
+        let mut summary = Summary::default();
         for file_def in &self.config.files {
-            if let Err(e) = self.read_shape_file(&mut osm, &file_def) {
+            let mut layer = LayerSummary::new(&file_def.name);
+            let result = match self.read_layer(file_def, processors, sink, &mut layer) {
+                Some(result) => result,
+                None => continue,
+            };
+            if let Err(e) = result {
                 if let ErrorKind::NotFound = e.kind() {
+                    summary.missing.push(file_def.name.clone());
                     continue;
                 } else {
                     return Err(e);
                 }
             }
+            summary.layers.push(layer);
         }
-        Ok(osm.build())
+        Ok(summary)
     }
 
-    fn find_region<P: AsRef<Path>>(region: &Option<String>, path: P) -> io::Result<String> {
-        if let Some(region) = region {
-            return Ok(region.clone());
+    /// Parses the config at `path`, or the default config if `None`.
+    pub fn parse_config<P: AsRef<Path>>(path: &Option<P>) -> io::Result<Config> {
+        if let Some(path) = path {
+            Ok(Config::open(path)?)
+        } else {
+            Ok(Config::parse_string(DEFAULT_CONFIG)?)
         }
+    }
 
-        let file_names: Vec<String> = fs::read_dir(path)?
-            .map(|e| {
-                // Turns a DirEntry into the file name. (Maybe a better way?)
-                e.as_ref()
-                    .unwrap()
-                    .path()
-                    .file_stem()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .to_owned()
-            })
-            .filter(|s| s.matches('_').count() == 1)
-            .take(1)
-            .collect();
-        let split: Vec<&str> = file_names[0].split('_').collect();
-        Ok((*split.last().unwrap()).to_owned())
+    /// The effective config at `path`, or the default config if `None`, as yaml.
+    pub fn resolve_config<P: AsRef<Path>>(path: &Option<P>) -> io::Result<String> {
+        if let Some(path) = path {
+            Config::resolve_file(path)
+        } else {
+            Ok(DEFAULT_CONFIG.to_owned())
+        }
     }
 
-    fn parse_config<P: AsRef<Path>>(path: &Option<P>) -> io::Result<Config> {
+    /// The source of the config at `path`, or of the default config if `None`.
+    pub fn config_source<P: AsRef<Path>>(path: &Option<P>) -> io::Result<String> {
         if let Some(path) = path {
-            Ok(Config::open(path)?)
+            fs::read_to_string(path)
         } else {
-            Ok(Config::parse_string(DEFAULT_CONFIG)?)
+            Ok(DEFAULT_CONFIG.to_owned())
         }
     }
 
-    fn read_shape_file(&self, osm: &mut OsmBuilder, file_def: &FileConfig) -> io::Result<()> {
-        let base_name = format!("{}_{}", file_def.name, &self.region);
-        let shape = ShapeFile::open(&self.folder, &base_name)?;
+    /// Converts a layer from its shape file or the source it is generated from. `None` if it is a
+    /// generated layer that is not enabled, those have no shape file.
+    fn read_layer<S: Sink>(
+        &self,
+        file_def: &FileConfig,
+        processors: &mut [&mut dyn RecordProcessor],
+        sink: &mut S,
+        summary: &mut LayerSummary,
+    ) -> Option<io::Result<()>> {
+        let name = file_def.name.as_str();
+        let result = match (&self.contours, &self.peaks, &self.coastline) {
+            (Some(contours), _, _) if name == contour::LAYER => {
+                self.read_contours(contours, file_def, processors, sink, summary)
+            }
+            (_, Some(peaks), _) if name == peak::LAYER => {
+                self.read_peaks(peaks, file_def, processors, sink, summary)
+            }
+            (_, _, Some(coastline)) if name == coastline::LAYER => {
+                self.read_coastline(coastline, file_def, processors, sink, summary)
+            }
+            _ if name == peak::LAYER || name == coastline::LAYER => return None,
+            _ => match &self.cache {
+                Some(cache)
+                    if processors.is_empty() && self.raw_attributes == RawAttributes::Off =>
+                {
+                    self.read_cached(cache, file_def, sink, summary)
+                }
+                _ => self.read_shape_file(file_def, processors, sink, summary),
+            },
+        };
+        Some(result)
+    }
+
+    /// Reads a layer from the cache, or converts it and stores it in the cache.
+    fn read_cached<S: Sink>(
+        &self,
+        cache: &Cache,
+        file_def: &FileConfig,
+        sink: &mut S,
+        summary: &mut LayerSummary,
+    ) -> io::Result<()> {
+        let file = self.layer_file(&file_def.name)?;
+        let key = Cache::key(&self.source, &file.base_name, &self.config, file_def)?;
+        if let Some((cached, features)) = cache.load(&file.base_name, &key) {
+            *summary = cached;
+            for feature in features {
+                sink.add(feature)?;
+            }
+            return Ok(());
+        }
+
+        let mut features = Vec::new();
+        self.read_shape_file(file_def, &mut [], &mut features, summary)?;
+        cache.store(&file.base_name, &key, summary, &features)?;
+        for feature in features {
+            sink.add(feature)?;
+        }
+        Ok(())
+    }
+
+    fn read_shape_file<S: Sink>(
+        &self,
+        file_def: &FileConfig,
+        processors: &mut [&mut dyn RecordProcessor],
+        sink: &mut S,
+        summary: &mut LayerSummary,
+    ) -> io::Result<()> {
+        let shape = self.open_shape_file(file_def)?;
 
         for record in shape {
-            let kkod = record.attributes.get("KKOD").unwrap();
-
-            if let Some(conf) = file_def.kkods.get(kkod) {
-                let mut tags: Vec<Tag> = conf.tags();
-                tags.push(("kkod".to_owned(), kkod.to_owned()).into());
-                tags.push(("layer".to_owned(), file_def.name.to_owned()).into());
-
-                match record.shape {
-                    Shape::Point(c) => {
-                        osm.add_point(c, tags.clone());
-                    }
-                    Shape::PolyLine(poly) => {
-                        for points in poly.parts {
-                            osm.add_polyline(points, tags.clone());
-                        }
-                    }
-                    Shape::Polygon(poly) => {
-                        osm.add_polygon(poly.parts, tags);
-                    }
+            self.convert_record(file_def, record, processors, sink, summary)?;
+        }
+        Ok(())
+    }
+
+    /// Converts the contours of the height model as records of the contour layer, see `contour`.
+    fn read_contours<S: Sink>(
+        &self,
+        contours: &Contours,
+        file_def: &FileConfig,
+        processors: &mut [&mut dyn RecordProcessor],
+        sink: &mut S,
+        summary: &mut LayerSummary,
+    ) -> io::Result<()> {
+        // Not `NotFound`, that would report the layer as missing.
+        let model =
+            HeightModel::open(&contours.grids).map_err(|e| io::Error::other(e.to_string()))?;
+        let found = contour::contours(&model, contours.interval, contours.smoothing);
+        for (index, contour) in found.into_iter().enumerate() {
+            let points = contour
+                .points
+                .iter()
+                .map(|&(north, east)| to_wgs(north, east))
+                .collect();
+            let mut attributes = HashMap::new();
+            attributes.insert("KKOD".to_owned(), contour::KKOD.to_owned());
+            attributes.insert("HOJDVARDE".to_owned(), contour.height.to_string());
+            let record = Record {
+                index,
+                shape: Shape::PolyLine(Poly::new(vec![points])),
+                attributes,
+            };
+            self.convert_record(file_def, record, processors, sink, summary)?;
+        }
+        Ok(())
+    }
+
+    /// Finds the summits of the height model and converts them as records of the peak layer, named
+    /// after the texts of the region, see `peak`.
+    fn read_peaks<S: Sink>(
+        &self,
+        peaks: &Peaks,
+        file_def: &FileConfig,
+        processors: &mut [&mut dyn RecordProcessor],
+        sink: &mut S,
+        summary: &mut LayerSummary,
+    ) -> io::Result<()> {
+        // Not `NotFound`, that would report the layer as missing.
+        let model = HeightModel::open(&peaks.grids).map_err(|e| io::Error::other(e.to_string()))?;
+        let mut found = peak::find(&model, peaks.radius, peaks.min_drop);
+        peak::name(&mut found, &self.texts()?);
+
+        for (index, peak) in found.into_iter().enumerate() {
+            let mut attributes = HashMap::new();
+            attributes.insert("KKOD".to_owned(), peak::KKOD.to_owned());
+            attributes.insert("HOJDVARDE".to_owned(), peak.height.round().to_string());
+            if let Some(name) = &peak.name {
+                attributes.insert("TEXT".to_owned(), name.clone());
+            }
+            let record = Record {
+                index,
+                shape: Shape::Point(to_wgs(peak.north, peak.east)),
+                attributes,
+            };
+
+            if let Some(mut feature) = self.feature(file_def, record, sink, summary)? {
+                feature
+                    .tags
+                    .push(("ele".to_owned(), peak.height.round().to_string()).into());
+                if let Some(name) = peak.name {
+                    feature.tags.push(("name".to_owned(), name).into());
                 }
+                self.add(feature, processors, sink, summary)?;
             }
         }
         Ok(())
     }
+
+    /// Extracts the coastlines of the sea from the land and water cover and converts them as
+    /// records of the coastline layer, see `coastline`.
+    fn read_coastline<S: Sink>(
+        &self,
+        coastline: &Coastline,
+        file_def: &FileConfig,
+        processors: &mut [&mut dyn RecordProcessor],
+        sink: &mut S,
+        summary: &mut LayerSummary,
+    ) -> io::Result<()> {
+        // Water is the kkods of the cover that the config tags as `natural=water`.
+        let water_kkods: Vec<&String> = self
+            .config
+            .files
+            .iter()
+            .filter(|f| f.name == coastline::COVER_LAYER)
+            .flat_map(|f| &f.kkods)
+            .filter(|(_, k)| k.tags.get("natural").map(|v| v.as_str()) == Some("water"))
+            .map(|(kkod, _)| kkod)
+            .collect();
+        let is_water = |record: &Record| {
+            record
+                .attributes
+                .get("KKOD")
+                .is_some_and(|kkod| water_kkods.contains(&kkod))
+        };
+
+        let mut polygons = Vec::new();
+        for record in self.open_layer(coastline::COVER_LAYER)? {
+            let water = is_water(&record);
+            if let Shape::Polygon(poly) = record.shape {
+                polygons.push((record.index, poly.parts, water));
+            }
+        }
+        let coastlines = Coastlines::new(polygons, coastline.min_area);
+
+        for (index, line) in coastlines.lines().into_iter().enumerate() {
+            let mut attributes = HashMap::new();
+            attributes.insert("KKOD".to_owned(), coastline::KKOD.to_owned());
+            let record = Record {
+                index,
+                shape: Shape::PolyLine(Poly::new(vec![line])),
+                attributes,
+            };
+            self.convert_record(file_def, record, processors, sink, summary)?;
+        }
+        Ok(())
+    }
+
+    /// Texts of the text layer as north, east and text, none if the region has no text layer.
+    fn texts(&self) -> io::Result<Vec<(f64, f64, String)>> {
+        let shape = match self.open_layer(peak::TEXT_LAYER) {
+            Ok(shape) => shape,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut texts = Vec::new();
+        for record in shape {
+            if let (Shape::Point(c), Some(text)) = (&record.shape, record.attributes.get("TEXT")) {
+                if !text.is_empty() {
+                    let (north, east) = from_wgs(c);
+                    texts.push((north, east, text.clone()));
+                }
+            }
+        }
+        Ok(texts)
+    }
+
+    /// Converts a record with the config of its kkod and adds it to `sink`.
+    fn convert_record<S: Sink>(
+        &self,
+        file_def: &FileConfig,
+        record: Record,
+        processors: &mut [&mut dyn RecordProcessor],
+        sink: &mut S,
+        summary: &mut LayerSummary,
+    ) -> io::Result<()> {
+        match self.feature(file_def, record, sink, summary)? {
+            Some(feature) => self.add(feature, processors, sink, summary),
+            None => Ok(()),
+        }
+    }
+
+    /// The feature of a record with the tags of its kkod. `None` if the record has no kkod or its
+    /// kkod is not in the config, those are added to `sink` as unmapped if raw attributes are
+    /// enabled.
+    fn feature<S: Sink>(
+        &self,
+        file_def: &FileConfig,
+        record: Record,
+        sink: &mut S,
+        summary: &mut LayerSummary,
+    ) -> io::Result<Option<Feature>> {
+        let kkod = match record.attributes.get("KKOD") {
+            Some(kkod) => kkod.to_owned(),
+            None => {
+                summary.skipped += 1;
+                return Ok(None);
+            }
+        };
+
+        let conf = match file_def.kkods.get(&kkod) {
+            Some(conf) => conf,
+            None => {
+                if self.raw_attributes == RawAttributes::Off {
+                    *summary.unknown.entry(kkod).or_insert(0) += 1;
+                } else {
+                    *summary.unmapped.entry(kkod.clone()).or_insert(0) += 1;
+                    let feature = Feature {
+                        layer: file_def.name.clone(),
+                        tags: vec![("lm:unmapped", "yes").into()],
+                        kkod,
+                        index: record.index,
+                        attributes: record.attributes,
+                        shape: record.shape,
+                    };
+                    sink.add(self.finish(feature))?;
+                }
+                return Ok(None);
+            }
+        };
+
+        let mut tags = Vec::new();
+        if self.raw_attributes != RawAttributes::Only {
+            tags = conf.tags_for(&record.attributes);
+            if let Some(key) = &self.config.title_tag {
+                if let Some(title) = conf.title(self.config.language.as_deref()) {
+                    tags.push((key.as_str(), title).into());
+                }
+            }
+        }
+
+        Ok(Some(Feature {
+            layer: file_def.name.clone(),
+            tags,
+            kkod,
+            index: record.index,
+            attributes: record.attributes,
+            shape: record.shape,
+        }))
+    }
+
+    /// Processes a feature and adds it to `sink` unless it is dropped.
+    fn add<S: Sink>(
+        &self,
+        feature: Feature,
+        processors: &mut [&mut dyn RecordProcessor],
+        sink: &mut S,
+        summary: &mut LayerSummary,
+    ) -> io::Result<()> {
+        match self.process(feature, processors)? {
+            Some(feature) => {
+                summary.converted += 1;
+                sink.add(self.finish(feature))
+            }
+            None => {
+                summary.dropped += 1;
+                Ok(())
+            }
+        }
+    }
+
+    /// Adds the `kkod` and `LAYER_TAG` tags, and the raw attributes if enabled.
+    fn finish(&self, mut feature: Feature) -> Feature {
+        feature
+            .tags
+            .push(("kkod".to_owned(), feature.kkod.clone()).into());
+        feature
+            .tags
+            .push((LAYER_TAG.to_owned(), feature.layer.clone()).into());
+        if self.raw_attributes != RawAttributes::Off {
+            feature.tags.extend(raw_tags(&feature.attributes));
+        }
+        feature
+    }
+
+    /// Runs the script and then the processors on a feature.
+    fn process(
+        &self,
+        mut feature: Feature,
+        processors: &mut [&mut dyn RecordProcessor],
+    ) -> io::Result<Option<Feature>> {
+        if let Some(script) = &self.script {
+            let input = Input {
+                layer: &feature.layer,
+                kkod: &feature.kkod,
+                attributes: &feature.attributes,
+                tags: &feature.tags,
+                shape: &feature.shape,
+            };
+            feature.tags = match script.process(&input)? {
+                Some(tags) => tags,
+                None => return Ok(None),
+            };
+        }
+
+        for processor in processors.iter_mut() {
+            feature = match processor.process(feature)? {
+                Some(feature) => feature,
+                None => return Ok(None),
+            };
+        }
+        Ok(Some(feature))
+    }
 }
@@ -0,0 +1,395 @@
+//! Writer for the OSM PBF format.
+//! See: https://wiki.openstreetmap.org/wiki/PBF_Format
+//!
+//! Entities are written sorted by type and then id. Nodes are written as dense nodes and every
+//! blob is zlib compressed.
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use vadeen_osm::geo::Coordinate;
+use vadeen_osm::{Node, Osm, Relation, RelationMember, Tag, Way};
+
+/// Max number of entities in one primitive block. The spec recommends 8000.
+const BLOCK_SIZE: usize = 8000;
+
+/// Coordinates are stored in units of 100 nanodegrees, same as `Coordinate`.
+const GRANULARITY: i64 = 100;
+
+const WRITING_PROGRAM: &str = concat!("lantmateriet_osm ", env!("CARGO_PKG_VERSION"));
+
+pub fn write<P: AsRef<Path>>(path: P, osm: &Osm) -> io::Result<()> {
+    let file = File::create(path)?;
+    let mut writer = PbfWriter::new(BufWriter::new(file));
+    writer.write(osm)?;
+    writer.into_inner().flush()
+}
+
+pub struct PbfWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> PbfWriter<W> {
+    pub fn new(writer: W) -> Self {
+        PbfWriter { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    pub fn write(&mut self, osm: &Osm) -> io::Result<()> {
+        self.write_header(osm)?;
+
+        let mut nodes: Vec<&Node> = osm.nodes.iter().collect();
+        nodes.sort_by_key(|n| n.id);
+        for chunk in nodes.chunks(BLOCK_SIZE) {
+            let mut block = BlockBuilder::default();
+            let group = block.dense_nodes(chunk);
+            self.write_blob("OSMData", &block.build(group))?;
+        }
+
+        let mut ways: Vec<&Way> = osm.ways.iter().collect();
+        ways.sort_by_key(|w| w.id);
+        for chunk in ways.chunks(BLOCK_SIZE) {
+            let mut block = BlockBuilder::default();
+            let group = block.ways(chunk);
+            self.write_blob("OSMData", &block.build(group))?;
+        }
+
+        let mut relations: Vec<&Relation> = osm.relations.iter().collect();
+        relations.sort_by_key(|r| r.id);
+        for chunk in relations.chunks(BLOCK_SIZE) {
+            let mut block = BlockBuilder::default();
+            let group = block.relations(chunk);
+            self.write_blob("OSMData", &block.build(group))?;
+        }
+
+        Ok(())
+    }
+
+    fn write_header(&mut self, osm: &Osm) -> io::Result<()> {
+        let mut header = Message::default();
+        if let Some(boundary) = &osm.boundary {
+            // Header bbox is in nanodegrees.
+            let mut bbox = Message::default();
+            bbox.sint64(1, nano_degrees(boundary.min.lon()));
+            bbox.sint64(2, nano_degrees(boundary.max.lon()));
+            bbox.sint64(3, nano_degrees(boundary.max.lat()));
+            bbox.sint64(4, nano_degrees(boundary.min.lat()));
+            header.message(1, &bbox);
+        }
+        header.bytes(4, b"OsmSchema-V0.6");
+        header.bytes(4, b"DenseNodes");
+        header.bytes(5, b"Sort.Type_then_ID");
+        header.bytes(16, WRITING_PROGRAM.as_bytes());
+
+        self.write_blob("OSMHeader", &header)
+    }
+
+    fn write_blob(&mut self, blob_type: &str, data: &Message) -> io::Result<()> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&data.buf)?;
+        let compressed = encoder.finish()?;
+
+        let mut blob = Message::default();
+        blob.int64(2, data.buf.len() as i64);
+        blob.bytes(3, &compressed);
+
+        let mut blob_header = Message::default();
+        blob_header.bytes(1, blob_type.as_bytes());
+        blob_header.int64(3, blob.buf.len() as i64);
+
+        self.writer
+            .write_all(&(blob_header.buf.len() as u32).to_be_bytes())?;
+        self.writer.write_all(&blob_header.buf)?;
+        self.writer.write_all(&blob.buf)
+    }
+}
+
+/// Builds a primitive block, keeping track of the string table.
+struct BlockBuilder {
+    strings: Vec<String>,
+    string_index: HashMap<String, u32>,
+}
+
+impl Default for BlockBuilder {
+    fn default() -> Self {
+        // Index 0 is reserved as delimiter in dense nodes and must be the empty string.
+        BlockBuilder {
+            strings: vec![String::new()],
+            string_index: HashMap::new(),
+        }
+    }
+}
+
+impl BlockBuilder {
+    fn string_id(&mut self, s: &str) -> u32 {
+        if let Some(id) = self.string_index.get(s) {
+            return *id;
+        }
+
+        let id = self.strings.len() as u32;
+        self.strings.push(s.to_owned());
+        self.string_index.insert(s.to_owned(), id);
+        id
+    }
+
+    fn tag_ids(&mut self, tags: &[Tag]) -> (Vec<u64>, Vec<u64>) {
+        tags.iter()
+            .map(|t| {
+                (
+                    u64::from(self.string_id(&t.key)),
+                    u64::from(self.string_id(&t.value)),
+                )
+            })
+            .unzip()
+    }
+
+    fn dense_nodes(&mut self, nodes: &[&Node]) -> Message {
+        let mut ids = Vec::with_capacity(nodes.len());
+        let mut lats = Vec::with_capacity(nodes.len());
+        let mut lons = Vec::with_capacity(nodes.len());
+        let mut keys_vals = Vec::new();
+
+        for node in nodes {
+            ids.push(node.id);
+            lats.push(units(&node.coordinate).0);
+            lons.push(units(&node.coordinate).1);
+
+            for tag in &node.meta.tags {
+                keys_vals.push(u64::from(self.string_id(&tag.key)));
+                keys_vals.push(u64::from(self.string_id(&tag.value)));
+            }
+            keys_vals.push(0);
+        }
+
+        let mut dense = Message::default();
+        dense.packed_sint64(1, &delta(&ids));
+        dense.packed_sint64(8, &delta(&lats));
+        dense.packed_sint64(9, &delta(&lons));
+        dense.packed_uint64(10, &keys_vals);
+
+        let mut group = Message::default();
+        group.message(2, &dense);
+        group
+    }
+
+    fn ways(&mut self, ways: &[&Way]) -> Message {
+        let mut group = Message::default();
+        for way in ways {
+            let (keys, vals) = self.tag_ids(&way.meta.tags);
+
+            let mut msg = Message::default();
+            msg.int64(1, way.id);
+            msg.packed_uint64(2, &keys);
+            msg.packed_uint64(3, &vals);
+            msg.packed_sint64(8, &delta(&way.refs));
+            group.message(3, &msg);
+        }
+        group
+    }
+
+    fn relations(&mut self, relations: &[&Relation]) -> Message {
+        let mut group = Message::default();
+        for relation in relations {
+            let (keys, vals) = self.tag_ids(&relation.meta.tags);
+
+            let mut roles = Vec::with_capacity(relation.members.len());
+            let mut member_ids = Vec::with_capacity(relation.members.len());
+            let mut types = Vec::with_capacity(relation.members.len());
+            for member in &relation.members {
+                let (id, role, member_type) = match member {
+                    RelationMember::Node(id, role) => (*id, role, 0),
+                    RelationMember::Way(id, role) => (*id, role, 1),
+                    RelationMember::Relation(id, role) => (*id, role, 2),
+                };
+                roles.push(u64::from(self.string_id(role)));
+                member_ids.push(id);
+                types.push(member_type);
+            }
+
+            let mut msg = Message::default();
+            msg.int64(1, relation.id);
+            msg.packed_uint64(2, &keys);
+            msg.packed_uint64(3, &vals);
+            msg.packed_uint64(8, &roles);
+            msg.packed_sint64(9, &delta(&member_ids));
+            msg.packed_uint64(10, &types);
+            group.message(4, &msg);
+        }
+        group
+    }
+
+    fn build(self, group: Message) -> Message {
+        let mut string_table = Message::default();
+        for s in &self.strings {
+            string_table.bytes(1, s.as_bytes());
+        }
+
+        let mut block = Message::default();
+        block.message(1, &string_table);
+        block.message(2, &group);
+        block.int64(17, GRANULARITY);
+        block
+    }
+}
+
+/// Minimal protobuf encoder, only supports what the PBF format needs.
+#[derive(Default)]
+struct Message {
+    buf: Vec<u8>,
+}
+
+impl Message {
+    fn key(&mut self, field: u32, wire_type: u32) {
+        self.varint(u64::from(field << 3 | wire_type));
+    }
+
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push((value as u8 & 0x7F) | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+
+    fn int64(&mut self, field: u32, value: i64) {
+        self.key(field, 0);
+        self.varint(value as u64);
+    }
+
+    fn sint64(&mut self, field: u32, value: i64) {
+        self.key(field, 0);
+        self.varint(zigzag(value));
+    }
+
+    fn bytes(&mut self, field: u32, value: &[u8]) {
+        self.key(field, 2);
+        self.varint(value.len() as u64);
+        self.buf.extend_from_slice(value);
+    }
+
+    fn message(&mut self, field: u32, message: &Message) {
+        self.bytes(field, &message.buf);
+    }
+
+    fn packed_uint64(&mut self, field: u32, values: &[u64]) {
+        if values.is_empty() {
+            return;
+        }
+
+        let mut packed = Message::default();
+        for v in values {
+            packed.varint(*v);
+        }
+        self.bytes(field, &packed.buf);
+    }
+
+    fn packed_sint64(&mut self, field: u32, values: &[i64]) {
+        let values: Vec<u64> = values.iter().map(|v| zigzag(*v)).collect();
+        self.packed_uint64(field, &values);
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn delta(values: &[i64]) -> Vec<i64> {
+    let mut last = 0;
+    values
+        .iter()
+        .map(|v| {
+            let d = v - last;
+            last = *v;
+            d
+        })
+        .collect()
+}
+
+/// Coordinate as (lat, lon) in units of `GRANULARITY` nanodegrees.
+fn units(coordinate: &Coordinate) -> (i64, i64) {
+    (
+        nano_degrees(coordinate.lat()) / GRANULARITY,
+        nano_degrees(coordinate.lon()) / GRANULARITY,
+    )
+}
+
+fn nano_degrees(degrees: f64) -> i64 {
+    (degrees * 1_000_000_000.0).round() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vadeen_osm::Meta;
+
+    /// The type of every blob, read from the blob headers.
+    fn blob_types(mut data: &[u8]) -> Vec<String> {
+        let varint = |data: &[u8], at: &mut usize| {
+            let mut value = 0u64;
+            let mut shift = 0;
+            loop {
+                let byte = data[*at];
+                *at += 1;
+                value |= u64::from(byte & 0x7F) << shift;
+                shift += 7;
+                if byte < 0x80 {
+                    return value as usize;
+                }
+            }
+        };
+
+        let mut types = Vec::new();
+        while !data.is_empty() {
+            let len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+            let header = &data[4..4 + len];
+            // Field 1, the type, and field 3, the size of the blob.
+            let mut at = 1;
+            let type_len = varint(header, &mut at);
+            types.push(String::from_utf8(header[at..at + type_len].to_vec()).unwrap());
+            at += type_len + 1;
+            let blob_len = varint(header, &mut at);
+            data = &data[4 + len + blob_len..];
+        }
+        types
+    }
+
+    #[test]
+    fn writes_header_then_data_blocks() {
+        let mut osm = Osm::default();
+        osm.add_node(Node {
+            id: 1,
+            coordinate: Coordinate::new(60.6749, 17.1413),
+            meta: Meta::default(),
+        });
+        osm.add_way(Way {
+            id: 1,
+            refs: vec![1],
+            meta: Meta::default(),
+        });
+
+        let mut writer = PbfWriter::new(Vec::new());
+        writer.write(&osm).unwrap();
+        assert_eq!(
+            blob_types(&writer.into_inner()),
+            vec!["OSMHeader", "OSMData", "OSMData"]
+        );
+    }
+
+    #[test]
+    fn encodes_units_deltas_and_zigzag() {
+        assert_eq!(
+            units(&Coordinate::new(60.6749, 17.1413)),
+            (606_749_000, 171_413_000)
+        );
+        assert_eq!(delta(&[5, 7, 4]), vec![5, 2, -3]);
+        assert_eq!(zigzag(-3), 5);
+        assert_eq!(zigzag(2), 4);
+    }
+}
@@ -0,0 +1,184 @@
+//! Summits found in a height model, see `dem`.
+//!
+//! A summit is the highest point within `radius` meters that rises at least `min_drop` meters above
+//! the lowest point within the radius. Summits are named after the nearest text of the text layer,
+//! `tx`, within `NAME_DISTANCE` meters. They are converted as records of the config's `peak`
+//! layer, with the height in `HOJDVARDE` and the name in `TEXT`, and get `ele` and `name` tags.
+
+use crate::dem::{Grid, HeightModel};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+/// Layer and kkod the summits are converted as.
+pub const LAYER: &str = "peak";
+pub const KKOD: &str = "peak";
+
+/// Layer of the texts the summits are named after.
+pub const TEXT_LAYER: &str = "tx";
+
+pub const DEFAULT_RADIUS: f64 = 250.0;
+pub const DEFAULT_MIN_DROP: f64 = 10.0;
+
+/// Max distance in meters between a summit and the text it is named after.
+const NAME_DISTANCE: f64 = 300.0;
+
+/// Points sampled per side of the radius when checking summits at the edge of a grid.
+const EDGE_SAMPLES: usize = 25;
+
+/// Height model files to find summits in, see `Lantmateriet::set_peaks`.
+#[derive(Debug, Clone)]
+pub struct Peaks {
+    pub grids: Vec<PathBuf>,
+    pub radius: f64,
+    pub min_drop: f64,
+}
+
+#[derive(Debug)]
+pub struct Peak {
+    /// North and east in sweref 99 TM.
+    pub north: f64,
+    pub east: f64,
+    pub height: f64,
+    pub name: Option<String>,
+}
+
+/// The summits of the height model, highest first.
+pub fn find(model: &HeightModel, radius: f64, min_drop: f64) -> Vec<Peak> {
+    let mut candidates = Vec::new();
+    for grid in model.grids() {
+        candidates.extend(find_in_grid(model, grid, radius, min_drop));
+    }
+
+    // Flat tops and overlapping grids give several candidates for the same summit.
+    candidates.sort_by(|a, b| b.height.total_cmp(&a.height));
+    let mut peaks: Vec<Peak> = Vec::new();
+    for candidate in candidates {
+        if peaks.iter().all(|p| distance(p, &candidate) > radius) {
+            peaks.push(candidate);
+        }
+    }
+    peaks
+}
+
+/// Names every peak after the nearest text, given as north, east and text.
+pub fn name(peaks: &mut [Peak], texts: &[(f64, f64, String)]) {
+    for peak in peaks {
+        peak.name = texts
+            .iter()
+            .map(|(north, east, text)| ((peak.north - north).hypot(peak.east - east), text))
+            .filter(|(d, _)| *d <= NAME_DISTANCE)
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, text)| text.clone());
+    }
+}
+
+/// Cells of the grid that are the highest and rise enough within the radius. Cells closer to the
+/// edge than the radius are checked against the whole height model.
+fn find_in_grid(model: &HeightModel, grid: &Grid, radius: f64, min_drop: f64) -> Vec<Peak> {
+    let r = (radius / grid.cell_size).round().max(1.0) as usize;
+    let (cols, rows) = (grid.cols, grid.rows);
+    if cols == 0 || rows == 0 {
+        return Vec::new();
+    }
+    let heights: Vec<f64> = (0..rows)
+        .flat_map(|row| (0..cols).map(move |col| (col, row)))
+        .map(|(col, row)| grid.height(col, row).unwrap_or(f64::NAN))
+        .collect();
+    let max = window(&heights, cols, r, f64::NEG_INFINITY, |a, b| a > b);
+    let min = window(&heights, cols, r, f64::INFINITY, |a, b| a < b);
+
+    let mut peaks = Vec::new();
+    for row in 0..rows {
+        for col in 0..cols {
+            let i = row * cols + col;
+            let height = heights[i];
+            if height.is_nan() || height < max[i] || height - min[i] < min_drop {
+                continue;
+            }
+
+            let (north, east) = grid.position(col as f64, row as f64);
+            let edge = col < r || row < r || col + r >= cols || row + r >= rows;
+            if edge && !is_highest(model, north, east, height, radius) {
+                continue;
+            }
+            peaks.push(Peak {
+                north,
+                east,
+                height,
+                name: None,
+            });
+        }
+    }
+    peaks
+}
+
+/// The best value within `radius` cells of every cell, rows of `cols` cells. Cells without data
+/// count as `none`.
+fn window(
+    values: &[f64],
+    cols: usize,
+    radius: usize,
+    none: f64,
+    better: fn(f64, f64) -> bool,
+) -> Vec<f64> {
+    let values: Vec<f64> = values
+        .iter()
+        .map(|&v| if v.is_nan() { none } else { v })
+        .collect();
+    let mut result = Vec::with_capacity(values.len());
+    for row in values.chunks(cols) {
+        result.extend(sliding(row, radius, better));
+    }
+
+    let rows = values.len() / cols;
+    for col in 0..cols {
+        let column: Vec<f64> = (0..rows).map(|row| result[row * cols + col]).collect();
+        for (row, value) in sliding(&column, radius, better).into_iter().enumerate() {
+            result[row * cols + col] = value;
+        }
+    }
+    result
+}
+
+/// The best value within `radius` of every value, with a queue of the candidates of the window.
+fn sliding(values: &[f64], radius: usize, better: fn(f64, f64) -> bool) -> Vec<f64> {
+    let mut result = Vec::with_capacity(values.len());
+    let mut queue: VecDeque<usize> = VecDeque::new();
+    let mut next = 0;
+    for i in 0..values.len() {
+        while next < values.len() && next <= i + radius {
+            while queue
+                .back()
+                .is_some_and(|&j| !better(values[j], values[next]))
+            {
+                queue.pop_back();
+            }
+            queue.push_back(next);
+            next += 1;
+        }
+        while queue.front().is_some_and(|&j| j + radius < i) {
+            queue.pop_front();
+        }
+        result.push(values[queue[0]]);
+    }
+    result
+}
+
+/// Whether no point of the height model within the radius is higher.
+fn is_highest(model: &HeightModel, north: f64, east: f64, height: f64, radius: f64) -> bool {
+    let step = radius / EDGE_SAMPLES as f64;
+    let n = EDGE_SAMPLES as i64;
+    for y in -n..=n {
+        for x in -n..=n {
+            let h = model.height_at(north + y as f64 * step, east + x as f64 * step);
+            if h.is_some_and(|h| h > height) {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+fn distance(a: &Peak, b: &Peak) -> f64 {
+    (a.north - b.north).hypot(a.east - b.east)
+}
@@ -0,0 +1,99 @@
+//! Extension points of the conversion, see `Lantmateriet::convert`.
+//!
+//! Every record with a kkod in the config becomes a `Feature` with the tags from the config. The
+//! feature is passed through the config's script and then each `RecordProcessor` in order, which
+//! can change it or drop it. Features that are left get the `kkod` and `LAYER_TAG` tags and are
+//! added to a `Sink`.
+//!
+//! With `RawAttributes` the attributes of the records are also added as `lm:` tags, e.g. `lm:HOJD`,
+//! and records of kkods that are not in the config are added tagged `lm:unmapped=yes`.
+
+use crate::shape::Shape;
+use std::collections::HashMap;
+use std::io;
+use vadeen_osm::{OsmBuilder, Tag};
+
+/// Tag with the layer of a feature. Not `layer`, that is osm's tag for what is above what.
+pub const LAYER_TAG: &str = "lm_layer";
+
+/// A record on its way through the conversion.
+#[derive(Debug)]
+pub struct Feature {
+    pub layer: String,
+    pub kkod: String,
+    /// Index of the record in the layer, see `ids`.
+    pub index: usize,
+    pub attributes: HashMap<String, String>,
+    pub shape: Shape,
+    pub tags: Vec<Tag>,
+}
+
+/// Whether the attributes of the records are added as tags, see `Lantmateriet::set_raw_attributes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawAttributes {
+    Off,
+    /// The attributes as well as the tags from the config.
+    WithTags,
+    /// Only the attributes, without the tags from the config.
+    Only,
+}
+
+/// Filters or transforms features, e.g. maps tags or simplifies geometries.
+pub trait RecordProcessor {
+    /// Returns the feature to keep, or `None` to drop it.
+    fn process(&mut self, feature: Feature) -> io::Result<Option<Feature>>;
+}
+
+/// Receives the converted features, e.g. an `OsmBuilder` or another output format.
+pub trait Sink {
+    fn add(&mut self, feature: Feature) -> io::Result<()>;
+}
+
+impl<F> RecordProcessor for F
+where
+    F: FnMut(Feature) -> io::Result<Option<Feature>>,
+{
+    fn process(&mut self, feature: Feature) -> io::Result<Option<Feature>> {
+        self(feature)
+    }
+}
+
+/// The attributes as `lm:` tags, sorted by name. Empty attributes are left out.
+pub fn raw_tags(attributes: &HashMap<String, String>) -> Vec<Tag> {
+    let mut attributes: Vec<(&String, &String)> =
+        attributes.iter().filter(|(_, v)| !v.is_empty()).collect();
+    attributes.sort();
+    attributes
+        .into_iter()
+        .map(|(k, v)| (format!("lm:{}", k), v.clone()).into())
+        .collect()
+}
+
+/// Polylines with several parts are added as one way per part.
+impl Sink for OsmBuilder {
+    fn add(&mut self, feature: Feature) -> io::Result<()> {
+        let tags = feature.tags;
+        match feature.shape {
+            Shape::Point(c) => {
+                self.add_point(c, tags);
+            }
+            Shape::PolyLine(poly) => {
+                for points in poly.parts {
+                    self.add_polyline(points, tags.clone());
+                }
+            }
+            Shape::Polygon(poly) => {
+                self.add_polygon(poly.parts, tags);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Collects the features, e.g. for tools that want the records with their tags.
+impl Sink for Vec<Feature> {
+    fn add(&mut self, feature: Feature) -> io::Result<()> {
+        self.push(feature);
+        Ok(())
+    }
+}
@@ -0,0 +1,235 @@
+//! Optional per record scripting with [rhai](https://rhai.rs), enabled with the `script` feature.
+//!
+//! The script set with `script:` in the config must define a function `process(record)`. The
+//! record is a map with:
+//!
+//! - `layer` and `kkod`
+//! - `attributes`, all attributes of the record as strings
+//! - `tags`, the tags from the config, including tags of matching rules
+//! - `geometry`, a map with `type` (`point`, `line` or `polygon`), `parts`, `points`,
+//!   `length` (meters, perimeter for polygons), `area` (square meters, 0 for lines and points)
+//!   and `lat`/`lon` of the first point
+//!
+//! It returns the tags to use as a map, `false` to drop the record, or `()`/`true` to keep the
+//! tags from the config. A tag set to `()` in the returned map is removed.
+//!
+//! ```rhai
+//! fn process(record) {
+//!     if record.geometry.type == "polygon" && record.geometry.area < 100.0 {
+//!         return false;
+//!     }
+//!     let tags = record.tags;
+//!     if "HOJDVARDE" in record.attributes {
+//!         tags.ele = record.attributes.HOJDVARDE;
+//!     }
+//!     tags
+//! }
+//! ```
+
+use crate::shape::Shape;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use vadeen_osm::Tag;
+
+/// The record that is passed to the script.
+pub struct Input<'a> {
+    pub layer: &'a str,
+    pub kkod: &'a str,
+    pub attributes: &'a HashMap<String, String>,
+    pub tags: &'a [Tag],
+    pub shape: &'a Shape,
+}
+
+#[cfg(feature = "script")]
+pub use self::rhai_script::Script;
+
+#[cfg(not(feature = "script"))]
+pub use self::disabled::Script;
+
+#[cfg(feature = "script")]
+mod rhai_script {
+    use super::Input;
+    use crate::shape::Shape;
+    use rhai::{Dynamic, Engine, Map, Scope, AST};
+    use std::fmt::Display;
+    use std::io;
+    use std::io::ErrorKind;
+    use std::path::Path;
+    use vadeen_osm::geo::Coordinate;
+    use vadeen_osm::Tag;
+
+    /// Mean radius of the earth in meters.
+    const EARTH_RADIUS: f64 = 6_371_000.0;
+
+    pub struct Script {
+        engine: Engine,
+        ast: AST,
+    }
+
+    impl Script {
+        pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Script> {
+            let path = path.as_ref();
+            let engine = Engine::new();
+            let ast = engine
+                .compile_file(path.to_path_buf())
+                .map_err(|e| script_error(path.display(), e))?;
+            Ok(Script { engine, ast })
+        }
+
+        /// Runs `process` for a record. Returns the tags to use, or `None` if the record should
+        /// be dropped.
+        pub fn process(&self, input: &Input) -> io::Result<Option<Vec<Tag>>> {
+            let result: Dynamic = self
+                .engine
+                .call_fn(&mut Scope::new(), &self.ast, "process", (record(input),))
+                .map_err(|e| script_error("process", e))?;
+
+            if result.is_unit() {
+                return Ok(Some(input.tags.to_vec()));
+            }
+            if let Ok(keep) = result.as_bool() {
+                return Ok(if keep {
+                    Some(input.tags.to_vec())
+                } else {
+                    None
+                });
+            }
+            match result.try_cast::<Map>() {
+                Some(map) => Ok(Some(
+                    map.into_iter()
+                        .filter(|(_, v)| !v.is_unit())
+                        .map(|(k, v)| (k.to_string(), v.to_string()).into())
+                        .collect(),
+                )),
+                None => Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "process must return a map of tags, true, false or ()",
+                )),
+            }
+        }
+    }
+
+    fn record(input: &Input) -> Map {
+        let attributes: Map = input
+            .attributes
+            .iter()
+            .map(|(k, v)| (k.as_str().into(), v.clone().into()))
+            .collect();
+        let tags: Map = input
+            .tags
+            .iter()
+            .map(|t| (t.key.as_str().into(), t.value.clone().into()))
+            .collect();
+
+        let mut record = Map::new();
+        record.insert("layer".into(), input.layer.to_owned().into());
+        record.insert("kkod".into(), input.kkod.to_owned().into());
+        record.insert("attributes".into(), attributes.into());
+        record.insert("tags".into(), tags.into());
+        record.insert("geometry".into(), geometry(input.shape).into());
+        record
+    }
+
+    fn geometry(shape: &Shape) -> Map {
+        let (kind, parts, area): (_, Vec<&[Coordinate]>, _) = match shape {
+            Shape::Point(c) => ("point", vec![std::slice::from_ref(c)], 0.0),
+            Shape::PolyLine(poly) => ("line", poly.parts.iter().map(Vec::as_slice).collect(), 0.0),
+            Shape::Polygon(poly) => {
+                let area: f64 = poly.parts.iter().map(|p| ring_area(p)).sum();
+                let parts = poly.parts.iter().map(Vec::as_slice).collect();
+                ("polygon", parts, area.abs())
+            }
+        };
+
+        let first = parts.iter().flat_map(|p| p.iter()).next();
+        let mut map = Map::new();
+        map.insert("type".into(), kind.to_owned().into());
+        map.insert("parts".into(), (parts.len() as i64).into());
+        map.insert(
+            "points".into(),
+            (parts.iter().map(|p| p.len()).sum::<usize>() as i64).into(),
+        );
+        map.insert(
+            "length".into(),
+            parts.iter().map(|p| length(p)).sum::<f64>().into(),
+        );
+        map.insert("area".into(), area.into());
+        map.insert("lat".into(), first.map_or(0.0, |c| c.lat()).into());
+        map.insert("lon".into(), first.map_or(0.0, |c| c.lon()).into());
+        map
+    }
+
+    /// Length of a line in meters, with an equirectangular approximation.
+    fn length(points: &[Coordinate]) -> f64 {
+        points
+            .windows(2)
+            .map(|w| {
+                let (x0, y0) = project(&w[0], w[0].lat());
+                let (x1, y1) = project(&w[1], w[0].lat());
+                ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt()
+            })
+            .sum()
+    }
+
+    /// Signed area of a ring in square meters. Holes are wound in the opposite direction of the
+    /// outer ring in shape files, so the parts of a polygon can be summed.
+    fn ring_area(points: &[Coordinate]) -> f64 {
+        let lat = match points.first() {
+            Some(c) => c.lat(),
+            None => return 0.0,
+        };
+        let projected: Vec<(f64, f64)> = points.iter().map(|c| project(c, lat)).collect();
+        projected
+            .windows(2)
+            .map(|w| w[0].0 * w[1].1 - w[1].0 * w[0].1)
+            .sum::<f64>()
+            / 2.0
+    }
+
+    /// Projects a coordinate to meters, scaled at latitude `lat`.
+    fn project(c: &Coordinate, lat: f64) -> (f64, f64) {
+        let x = c.lon().to_radians() * EARTH_RADIUS * lat.to_radians().cos();
+        let y = c.lat().to_radians() * EARTH_RADIUS;
+        (x, y)
+    }
+
+    fn script_error<C: Display, E: Display>(context: C, e: E) -> io::Error {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            format!("Script error in {}: {}", context, e),
+        )
+    }
+}
+
+#[cfg(not(feature = "script"))]
+mod disabled {
+    use super::Input;
+    use std::convert::Infallible;
+    use std::io;
+    use std::path::Path;
+    use vadeen_osm::Tag;
+
+    /// Placeholder when built without the `script` feature, it can't be created.
+    pub struct Script(Infallible);
+
+    impl Script {
+        pub fn open<P: AsRef<Path>>(_: P) -> io::Result<Script> {
+            Err(io::Error::other(
+                "The config has a script but this build is without the `script` feature",
+            ))
+        }
+
+        pub fn process(&self, _: &Input) -> io::Result<Option<Vec<Tag>>> {
+            match self.0 {}
+        }
+    }
+}
+
+/// Opens the script in the config, if any.
+pub(crate) fn open(path: &Option<impl AsRef<Path>>) -> io::Result<Option<Script>> {
+    match path {
+        Some(path) => Script::open(path).map(Some),
+        None => Ok(None),
+    }
+}
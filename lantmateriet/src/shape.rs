@@ -2,23 +2,27 @@ mod dbase;
 
 use crate::binary::*;
 use crate::shape::dbase::DBase;
+pub use crate::shape::dbase::FieldDescriptor;
 use crate::sweref99tm::to_wgs;
+use crate::vfs::{Source, VirtualFile};
 use std::collections::HashMap;
-use std::fs::File;
 use std::io;
 use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom};
-use std::path::Path;
 use vadeen_osm::geo::{Boundary, Coordinate};
 
 #[derive(Debug)]
 pub struct ShapeFile {
-    reader: BufReader<File>,
+    reader: BufReader<VirtualFile>,
     pub header: Header,
     dbase: DBase,
+    /// Index of the next record.
+    index: usize,
 }
 
 #[derive(Debug)]
 pub struct Record {
+    /// Position of the record in its layer, from 0.
+    pub index: usize,
     pub shape: Shape,
     pub attributes: HashMap<String, String>,
 }
@@ -46,17 +50,72 @@ impl Iterator for ShapeFile {
     type Item = Record;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.dbase.next().map(|r| Record {
+        let r = self.dbase.next()?;
+        let record = Record {
+            index: self.index,
             shape: self.read_shape().unwrap(),
             attributes: r.fields,
-        })
+        };
+        self.index += 1;
+        Some(record)
+    }
+}
+
+impl Header {
+    /// Name of the shape type of all records in the file, as in `Shape::type_name`.
+    pub fn type_name(&self) -> &'static str {
+        match self.shape_type {
+            1 => "Point",
+            3 => "PolyLine",
+            5 => "Polygon",
+            _ => "Unknown",
+        }
+    }
+}
+
+impl Shape {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Shape::Point(_) => "Point",
+            Shape::PolyLine(_) => "PolyLine",
+            Shape::Polygon(_) => "Polygon",
+        }
+    }
+}
+
+impl Poly {
+    pub fn new(parts: Vec<Vec<Coordinate>>) -> Poly {
+        let mut points = parts.iter().flatten();
+        let first = points.next().map_or((0.0, 0.0), |c| (c.lat(), c.lon()));
+        let (mut min, mut max) = (first, first);
+        for c in points {
+            min = (min.0.min(c.lat()), min.1.min(c.lon()));
+            max = (max.0.max(c.lat()), max.1.max(c.lon()));
+        }
+
+        let bounds = Boundary {
+            min: Coordinate::new(min.0, min.1),
+            max: Coordinate::new(max.0, max.1),
+            freeze: false,
+        };
+        Poly { bounds, parts }
     }
 }
 
 impl ShapeFile {
-    pub fn open<P: AsRef<Path>>(path: P, base_name: &str) -> io::Result<ShapeFile> {
-        let dbase_file = Self::open_file(path.as_ref(), base_name, ".dbf")?;
-        let shape_file = Self::open_file(path.as_ref(), base_name, ".shp")?;
+    pub fn record_count(&self) -> u32 {
+        self.dbase.record_count()
+    }
+
+    /// The attribute columns of the records.
+    pub fn fields(&self) -> &[FieldDescriptor] {
+        self.dbase.fields()
+    }
+
+    /// Opens `<base_name>.shp` and `<base_name>.dbf` in a folder or zip archive.
+    pub fn open(source: &Source, base_name: &str) -> io::Result<ShapeFile> {
+        let dbase_file = source.open_file(&format!("{}.dbf", base_name))?;
+        let shape_file = source.open_file(&format!("{}.shp", base_name))?;
 
         let dbase = DBase::open(dbase_file)?;
         let mut reader = BufReader::new(shape_file);
@@ -67,6 +126,7 @@ impl ShapeFile {
             reader,
             header,
             dbase,
+            index: 0,
         })
     }
 
@@ -83,26 +143,6 @@ impl ShapeFile {
         Ok(Header { bounds, shape_type })
     }
 
-    fn open_file(base_path: &Path, base_name: &str, ext: &str) -> io::Result<File> {
-        let file_name = base_name.to_owned() + ext;
-        let mut file_path = base_path.to_path_buf();
-        file_path.push(file_name);
-
-        match File::open(&file_path) {
-            Err(e) => match e.kind() {
-                ErrorKind::NotFound => Err(io::Error::new(
-                    ErrorKind::NotFound,
-                    format!(
-                        "Could not open shape file {:?}, file not found.",
-                        &file_path
-                    ),
-                )),
-                _ => Err(e),
-            },
-            ok => ok,
-        }
-    }
-
     fn read_shape(&mut self) -> io::Result<Shape> {
         let _rec_num = read_be_u32(&mut self.reader)?;
         let _rec_len = read_be_u32(&mut self.reader)?;
@@ -1,12 +1,12 @@
 use crate::binary::*;
+use crate::vfs::VirtualFile;
 use std::collections::HashMap;
-use std::fs::File;
 use std::io;
 use std::io::{BufReader, Read, Seek, SeekFrom};
 
 #[derive(Debug)]
 pub struct DBase {
-    reader: BufReader<File>,
+    reader: BufReader<VirtualFile>,
     header: Header,
 }
 
@@ -22,12 +22,14 @@ struct Header {
     field_descriptors: Vec<FieldDescriptor>,
 }
 
+/// A column of the table, `field_type` is the dBase type letter, e.g. `C` for text and `N` for
+/// numbers.
 #[derive(Debug)]
-struct FieldDescriptor {
-    field_name: String,
-    field_type: char,
-    field_size: u8,
-    field_decimal_count: u8,
+pub struct FieldDescriptor {
+    pub field_name: String,
+    pub field_type: char,
+    pub field_size: u8,
+    pub field_decimal_count: u8,
 }
 
 impl Iterator for DBase {
@@ -51,7 +53,15 @@ impl Iterator for DBase {
 }
 
 impl DBase {
-    pub fn open(file: File) -> io::Result<DBase> {
+    pub fn record_count(&self) -> u32 {
+        self.header.record_count
+    }
+
+    pub fn fields(&self) -> &[FieldDescriptor] {
+        &self.header.field_descriptors
+    }
+
+    pub fn open(file: VirtualFile) -> io::Result<DBase> {
         let mut reader = BufReader::new(file);
         let header = Self::read_header(&mut reader)?;
 
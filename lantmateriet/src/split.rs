@@ -0,0 +1,410 @@
+//! Splits an osm map into tiles the same way mkgmap's splitter does.
+//! See: https://www.mkgmap.org.uk/doc/splitter.html
+//!
+//! All coordinates in this module are in garmin map units, 360 / 2^24 degrees.
+
+use crate::pbf;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::Path;
+use vadeen_osm::geo::{Boundary, Coordinate};
+use vadeen_osm::{Node, Osm, Relation, RelationMember, Way};
+
+/// Tile borders are aligned to this many map units, same as splitter's default resolution 13.
+const ALIGNMENT: i32 = 1 << (24 - 13);
+
+/// Map name of the first tile, mkgmap wants 8 digits.
+pub const DEFAULT_MAP_ID: u32 = 63_240_001;
+
+/// Highest map name with 8 digits.
+pub const MAX_MAP_ID: u32 = 99_999_999;
+
+#[derive(Debug, Clone, Copy)]
+pub enum SplitMode {
+    /// Fixed grid with tiles of `size` map units.
+    Grid { size: i32 },
+    /// Split areas in half until no area contains more than `max_nodes` nodes.
+    Adaptive { max_nodes: usize },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Area {
+    pub id: u32,
+    pub min_lat: i32,
+    pub min_lon: i32,
+    pub max_lat: i32,
+    pub max_lon: i32,
+}
+
+#[derive(Debug)]
+pub struct Tile {
+    pub area: Area,
+    pub osm: Osm,
+}
+
+impl SplitMode {
+    /// Grid with tiles of `degrees` size.
+    pub fn grid(degrees: f64) -> SplitMode {
+        let size = align_up(to_map_units(degrees).max(1));
+        SplitMode::Grid { size }
+    }
+}
+
+impl Area {
+    fn contains(&self, (lat, lon): (i32, i32)) -> bool {
+        lat >= self.min_lat && lat < self.max_lat && lon >= self.min_lon && lon < self.max_lon
+    }
+
+    pub fn file_name(&self) -> String {
+        format!("{:08}.osm.pbf", self.id)
+    }
+
+    fn boundary(&self) -> Boundary {
+        Boundary {
+            min: Coordinate::new(to_degrees(self.min_lat), to_degrees(self.min_lon)),
+            max: Coordinate::new(to_degrees(self.max_lat), to_degrees(self.max_lon)),
+            freeze: false,
+        }
+    }
+}
+
+/// Splits `osm` into tiles. Ways and relations are included in every tile they touch, together
+/// with all nodes they reference. Tiles without any nodes are dropped. Fails if the map names of
+/// the tiles would go past `MAX_MAP_ID`.
+pub fn split(osm: &Osm, mode: SplitMode, first_id: u32) -> io::Result<Vec<Tile>> {
+    let positions: Vec<(i32, i32)> = osm.nodes.iter().map(map_units).collect();
+    let areas = match mode {
+        SplitMode::Grid { size } => grid_areas(&positions, size),
+        SplitMode::Adaptive { max_nodes } => adaptive_areas(&positions, max_nodes),
+    };
+
+    let index: HashMap<i64, usize> = osm
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (n.id, i))
+        .collect();
+    let way_index: HashMap<i64, usize> = osm
+        .ways
+        .iter()
+        .enumerate()
+        .map(|(i, w)| (w.id, i))
+        .collect();
+    let mut tiles: Vec<Tile> = areas
+        .into_iter()
+        .filter_map(|a| tile(osm, &positions, &index, &way_index, a))
+        .collect();
+
+    let count = tiles.len();
+    for (i, tile) in tiles.iter_mut().enumerate() {
+        tile.area.id = u32::try_from(i)
+            .ok()
+            .and_then(|i| first_id.checked_add(i))
+            .filter(|id| *id <= MAX_MAP_ID)
+            .ok_or_else(|| {
+                io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "{} tiles starting at map id {} go past {}",
+                        count, first_id, MAX_MAP_ID
+                    ),
+                )
+            })?;
+    }
+    Ok(tiles)
+}
+
+/// Writes every tile as pbf together with the `areas.list` and `template.args` files mkgmap
+/// expects from splitter.
+pub fn write_tiles<P: AsRef<Path>>(folder: P, tiles: &[Tile], description: &str) -> io::Result<()> {
+    let folder = folder.as_ref();
+    fs::create_dir_all(folder)?;
+
+    for tile in tiles {
+        pbf::write(folder.join(tile.area.file_name()), &tile.osm)?;
+    }
+
+    write_areas_list(folder.join("areas.list"), tiles)?;
+    write_template_args(folder.join("template.args"), tiles, description)
+}
+
+fn write_areas_list<P: AsRef<Path>>(path: P, tiles: &[Tile]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "# List of areas")?;
+    writeln!(writer, "# Generated by lantmateriet_osm")?;
+    writeln!(writer, "#")?;
+    for tile in tiles {
+        let a = &tile.area;
+        writeln!(
+            writer,
+            "{:08}: {},{} to {},{}",
+            a.id, a.min_lat, a.min_lon, a.max_lat, a.max_lon
+        )?;
+        writeln!(
+            writer,
+            "#       : {:.6},{:.6} to {:.6},{:.6}",
+            to_degrees(a.min_lat),
+            to_degrees(a.min_lon),
+            to_degrees(a.max_lat),
+            to_degrees(a.max_lon)
+        )?;
+        writeln!(writer)?;
+    }
+    writer.flush()
+}
+
+fn write_template_args<P: AsRef<Path>>(
+    path: P,
+    tiles: &[Tile],
+    description: &str,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for tile in tiles {
+        writeln!(writer, "mapname: {:08}", tile.area.id)?;
+        writeln!(writer, "description: {}", description)?;
+        writeln!(writer, "input-file: {}", tile.area.file_name())?;
+        writeln!(writer)?;
+    }
+    writer.flush()
+}
+
+fn grid_areas(positions: &[(i32, i32)], size: i32) -> Vec<Area> {
+    let (min_lat, min_lon, max_lat, max_lon) = match bounds(positions.iter()) {
+        Some(b) => b,
+        None => return Vec::new(),
+    };
+
+    let mut areas = Vec::new();
+    let mut lat = align_down(min_lat);
+    while lat <= max_lat {
+        let mut lon = align_down(min_lon);
+        while lon <= max_lon {
+            areas.push(Area {
+                id: 0,
+                min_lat: lat,
+                min_lon: lon,
+                max_lat: lat + size,
+                max_lon: lon + size,
+            });
+            lon += size;
+        }
+        lat += size;
+    }
+    areas
+}
+
+fn adaptive_areas(positions: &[(i32, i32)], max_nodes: usize) -> Vec<Area> {
+    let (min_lat, min_lon, max_lat, max_lon) = match bounds(positions.iter()) {
+        Some(b) => b,
+        None => return Vec::new(),
+    };
+
+    let root = Area {
+        id: 0,
+        min_lat: align_down(min_lat),
+        min_lon: align_down(min_lon),
+        max_lat: align_up(max_lat + 1),
+        max_lon: align_up(max_lon + 1),
+    };
+
+    let mut areas = Vec::new();
+    let mut queue = vec![(root, positions.to_vec())];
+    while let Some((area, mut nodes)) = queue.pop() {
+        let height = area.max_lat - area.min_lat;
+        let width = area.max_lon - area.min_lon;
+        if nodes.len() <= max_nodes || (height <= ALIGNMENT && width <= ALIGNMENT) {
+            areas.push(area);
+            continue;
+        }
+
+        // Split the longest side at the median node, aligned to the grid.
+        let split_lat = height >= width;
+        if split_lat {
+            nodes.sort_by_key(|p| p.0);
+        } else {
+            nodes.sort_by_key(|p| p.1);
+        }
+        let median = nodes[nodes.len() / 2];
+
+        let (low, high) = if split_lat {
+            let at = clamp_split(align_down(median.0), area.min_lat, area.max_lat);
+            let low = Area {
+                max_lat: at,
+                ..area
+            };
+            let high = Area {
+                min_lat: at,
+                ..area
+            };
+            (low, high)
+        } else {
+            let at = clamp_split(align_down(median.1), area.min_lon, area.max_lon);
+            let low = Area {
+                max_lon: at,
+                ..area
+            };
+            let high = Area {
+                min_lon: at,
+                ..area
+            };
+            (low, high)
+        };
+
+        let (low_nodes, high_nodes): (Vec<_>, Vec<_>) =
+            nodes.into_iter().partition(|p| low.contains(*p));
+        queue.push((high, high_nodes));
+        queue.push((low, low_nodes));
+    }
+
+    areas.sort_by_key(|a| (a.min_lat, a.min_lon));
+    areas
+}
+
+/// Keeps the split point strictly inside the area so both halves are non empty.
+fn clamp_split(at: i32, min: i32, max: i32) -> i32 {
+    at.max(min + ALIGNMENT).min(max - ALIGNMENT)
+}
+
+fn tile(
+    osm: &Osm,
+    positions: &[(i32, i32)],
+    index: &HashMap<i64, usize>,
+    way_index: &HashMap<i64, usize>,
+    area: Area,
+) -> Option<Tile> {
+    let mut node_ids: HashSet<i64> = osm
+        .nodes
+        .iter()
+        .zip(positions)
+        .filter(|(_, p)| area.contains(**p))
+        .map(|(n, _)| n.id)
+        .collect();
+    if node_ids.is_empty() {
+        return None;
+    }
+
+    let ways: Vec<Way> = osm
+        .ways
+        .iter()
+        .filter(|w| w.refs.iter().any(|r| node_ids.contains(r)))
+        .cloned()
+        .collect();
+    let way_ids: HashSet<i64> = ways.iter().map(|w| w.id).collect();
+
+    let relations: Vec<Relation> = osm
+        .relations
+        .iter()
+        .filter(|r| {
+            r.members.iter().any(|m| match m {
+                RelationMember::Node(id, _) => node_ids.contains(id),
+                RelationMember::Way(id, _) => way_ids.contains(id),
+                RelationMember::Relation(_, _) => false,
+            })
+        })
+        .cloned()
+        .collect();
+
+    // Relations must be complete, pull in all member ways.
+    let mut ways = ways;
+    let mut way_ids = way_ids;
+    for relation in &relations {
+        for member in &relation.members {
+            if let RelationMember::Way(id, _) = member {
+                if way_ids.insert(*id) {
+                    if let Some(i) = way_index.get(id) {
+                        ways.push(osm.ways[*i].clone());
+                    }
+                }
+            }
+        }
+    }
+
+    for way in &ways {
+        node_ids.extend(way.refs.iter());
+    }
+
+    let mut tile_osm = Osm::default();
+    for i in node_ids.iter().filter_map(|id| index.get(id)) {
+        tile_osm.add_node(osm.nodes[*i].clone());
+    }
+    for way in ways {
+        tile_osm.add_way(way);
+    }
+    for relation in relations {
+        tile_osm.add_relation(relation);
+    }
+    tile_osm.boundary = Some(area.boundary());
+
+    Some(Tile {
+        area,
+        osm: tile_osm,
+    })
+}
+
+fn bounds<'a, I: Iterator<Item = &'a (i32, i32)>>(positions: I) -> Option<(i32, i32, i32, i32)> {
+    positions.fold(None, |acc, &(lat, lon)| match acc {
+        None => Some((lat, lon, lat, lon)),
+        Some((min_lat, min_lon, max_lat, max_lon)) => Some((
+            min_lat.min(lat),
+            min_lon.min(lon),
+            max_lat.max(lat),
+            max_lon.max(lon),
+        )),
+    })
+}
+
+fn map_units(node: &Node) -> (i32, i32) {
+    (
+        to_map_units(node.coordinate.lat()),
+        to_map_units(node.coordinate.lon()),
+    )
+}
+
+fn to_map_units(degrees: f64) -> i32 {
+    (degrees * f64::from(1 << 24) / 360.0).round() as i32
+}
+
+fn to_degrees(map_units: i32) -> f64 {
+    f64::from(map_units) * 360.0 / f64::from(1 << 24)
+}
+
+fn align_down(value: i32) -> i32 {
+    value.div_euclid(ALIGNMENT) * ALIGNMENT
+}
+
+fn align_up(value: i32) -> i32 {
+    align_down(value + ALIGNMENT - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vadeen_osm::Meta;
+
+    /// Nodes a degree apart, one tile each in a one degree grid.
+    fn osm(nodes: i64) -> Osm {
+        let mut osm = Osm::default();
+        for id in 0..nodes {
+            osm.add_node(Node {
+                id,
+                coordinate: Coordinate::new(60.5, 15.5 + id as f64),
+                meta: Meta::default(),
+            });
+        }
+        osm
+    }
+
+    #[test]
+    fn map_ids_stay_within_eight_digits() {
+        let tiles = split(&osm(2), SplitMode::grid(1.0), MAX_MAP_ID - 1).unwrap();
+        let ids: Vec<u32> = tiles.iter().map(|t| t.area.id).collect();
+        assert_eq!(ids, vec![MAX_MAP_ID - 1, MAX_MAP_ID]);
+
+        assert!(split(&osm(2), SplitMode::grid(1.0), MAX_MAP_ID).is_err());
+        assert!(split(&osm(2), SplitMode::grid(1.0), u32::MAX).is_err());
+    }
+}
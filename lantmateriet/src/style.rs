@@ -0,0 +1,198 @@
+//! The mkgmap style and TYP file used for garmin maps.
+//! See: https://www.mkgmap.org.uk/doc/pdf/style-manual.pdf
+//!
+//! The `lines`, `points` and `polygons` rules and the TYP file are generated from the `garmin`
+//! section of each kkod in the config, so only kkods that are converted are referenced. The look
+//! of each type in the TYP file is described in `typ`.
+
+use crate::config::{Config, GarminKind};
+use crate::process::LAYER_TAG;
+use crate::typ;
+use crate::typ::{type_order, Types};
+use std::cmp::Reverse;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const OPTIONS: &str = include_str!("../../styles/options");
+const VERSION: &str = include_str!("../../styles/version");
+
+/// File name of the TYP source written by `write_typ_file`.
+pub const TYP_FILE_NAME: &str = "typfile.txt";
+
+/// Draw order used for polygon types missing in the config's `draw_order`.
+const DEFAULT_DRAW_ORDER: u8 = 1;
+
+/// One style rule, all kkods rendered as the same garmin type and level.
+struct Rule<'a> {
+    garmin_type: &'a str,
+    level: u8,
+    kkods: Vec<(&'a str, &'a str)>,
+    titles: Vec<&'a str>,
+}
+
+/// Writes the mkgmap style files into `folder`, suitable for mkgmap's `--style-file`.
+pub fn write_style<P: AsRef<Path>>(folder: P, config: &Config) -> io::Result<()> {
+    let folder = folder.as_ref();
+    fs::create_dir_all(folder)?;
+
+    fs::write(folder.join("lines"), rules(config, GarminKind::Line))?;
+    fs::write(folder.join("points"), rules(config, GarminKind::Point))?;
+    fs::write(folder.join("polygons"), rules(config, GarminKind::Polygon))?;
+    fs::write(folder.join("options"), OPTIONS)?;
+    fs::write(folder.join("version"), VERSION)
+}
+
+/// Writes the TYP source into `folder`, with the types of the config's `garmin_types`.
+pub fn write_typ_file<P: AsRef<Path>>(
+    folder: P,
+    config: &Config,
+    family_id: u32,
+) -> io::Result<()> {
+    let folder = folder.as_ref();
+    fs::create_dir_all(folder)?;
+
+    let types = Types::for_config(config)?;
+    fs::write(
+        folder.join(TYP_FILE_NAME),
+        typ_file(config, &types, family_id),
+    )
+}
+
+/// The mkgmap rules for all kkods of `kind`.
+pub fn rules(config: &Config, kind: GarminKind) -> String {
+    let mut rules: Vec<Rule> = Vec::new();
+    for file in &config.files {
+        let mut kkods: Vec<_> = file.kkods.iter().collect();
+        kkods.sort_by_key(|(kkod, _)| kkod_order(kkod));
+
+        for (kkod, conf) in kkods {
+            let garmin = match &conf.garmin {
+                Some(garmin) if garmin.kind == kind => garmin,
+                _ => continue,
+            };
+
+            let index = rules
+                .iter()
+                .position(|r| r.garmin_type == garmin.garmin_type && r.level == garmin.level);
+            let index = match index {
+                Some(index) => index,
+                None => {
+                    rules.push(Rule {
+                        garmin_type: &garmin.garmin_type,
+                        level: garmin.level,
+                        kkods: Vec::new(),
+                        titles: Vec::new(),
+                    });
+                    rules.len() - 1
+                }
+            };
+
+            let rule = &mut rules[index];
+            rule.kkods.push((file.name.as_str(), kkod.as_str()));
+            if let Some(title) = &conf.title {
+                rule.titles.push(title);
+            }
+        }
+    }
+
+    rules.sort_by_key(|r| Reverse(r.level));
+
+    let mut out = String::new();
+    for rule in rules {
+        if !rule.titles.is_empty() {
+            writeln!(out, "# {}", rule.titles.join(", ")).unwrap();
+        }
+        writeln!(
+            out,
+            "{} [{} level {}]",
+            condition(&rule.kkods),
+            rule.garmin_type,
+            rule.level
+        )
+        .unwrap();
+        writeln!(out).unwrap();
+    }
+    out
+}
+
+/// The TYP source, with the draw order and the types used by the config.
+pub fn typ_file(config: &Config, types: &Types, family_id: u32) -> String {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "[_id]\nFID={}\nProductCode=1\nCodePage=1252\n[end]\n",
+        family_id
+    )
+    .unwrap();
+    writeln!(out, "{}\n", draw_order(config)).unwrap();
+    out.push_str(&typ::sections(config, types));
+    out
+}
+
+fn draw_order(config: &Config) -> String {
+    let mut types: Vec<(&str, u8)> = Vec::new();
+    for file in &config.files {
+        for conf in file.kkods.values() {
+            if let Some(garmin) = &conf.garmin {
+                let garmin_type = garmin.garmin_type.as_str();
+                if garmin.kind != GarminKind::Polygon
+                    || types.iter().any(|(t, _)| *t == garmin_type)
+                {
+                    continue;
+                }
+
+                let order = config
+                    .draw_order
+                    .get(garmin_type)
+                    .copied()
+                    .unwrap_or(DEFAULT_DRAW_ORDER);
+                types.push((garmin_type, order));
+            }
+        }
+    }
+    types.sort_by_key(|(t, order)| (Reverse(*order), type_order(t)));
+
+    let mut out = String::from("[_drawOrder]\n");
+    for (garmin_type, order) in types {
+        writeln!(out, "Type={},{}", garmin_type, order).unwrap();
+    }
+    out.push_str("[end]");
+    out
+}
+
+/// Formats e.g. `(lm_layer=my & kkod=15) | (lm_layer=kl & kkod=2670)`.
+fn condition(kkods: &[(&str, &str)]) -> String {
+    let mut layers: Vec<(&str, Vec<&str>)> = Vec::new();
+    for &(layer, kkod) in kkods {
+        match layers.iter_mut().find(|(l, _)| *l == layer) {
+            Some((_, kkods)) => kkods.push(kkod),
+            None => layers.push((layer, vec![kkod])),
+        }
+    }
+
+    let conditions: Vec<String> = layers
+        .iter()
+        .map(|(layer, kkods)| {
+            let kkods: Vec<String> = kkods.iter().map(|k| format!("kkod={}", k)).collect();
+            if kkods.len() == 1 {
+                format!("{}={} & {}", LAYER_TAG, layer, kkods[0])
+            } else {
+                format!("{}={} & ({})", LAYER_TAG, layer, kkods.join(" | "))
+            }
+        })
+        .collect();
+
+    if conditions.len() == 1 {
+        conditions[0].clone()
+    } else {
+        let conditions: Vec<String> = conditions.iter().map(|c| format!("({})", c)).collect();
+        conditions.join(" | ")
+    }
+}
+
+/// Numeric kkods sorted by value, anything else after.
+fn kkod_order(kkod: &str) -> (u32, String) {
+    (kkod.parse().unwrap_or(u32::MAX), kkod.to_owned())
+}
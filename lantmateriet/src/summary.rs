@@ -0,0 +1,77 @@
+//! Counts of what happened to the records during a conversion.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, Default, Serialize)]
+pub struct Summary {
+    pub layers: Vec<LayerSummary>,
+    /// Layers in the config without a shape file in the folder.
+    pub missing: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct LayerSummary {
+    pub name: String,
+    pub converted: usize,
+    /// Records without a KKOD attribute.
+    pub skipped: usize,
+    /// Records dropped by the script or a processor.
+    pub dropped: usize,
+    /// Number of records per kkod that is not in the config.
+    pub unknown: BTreeMap<String, usize>,
+    /// Like `unknown`, but converted tagged `lm:unmapped=yes`, see `RawAttributes`.
+    pub unmapped: BTreeMap<String, usize>,
+}
+
+impl Summary {
+    /// True if any record was dropped because its kkod isn't in the config.
+    pub fn has_unknown(&self) -> bool {
+        self.layers.iter().any(|l| !l.unknown.is_empty())
+    }
+}
+
+impl LayerSummary {
+    pub fn new(name: &str) -> LayerSummary {
+        LayerSummary {
+            name: name.to_owned(),
+            ..LayerSummary::default()
+        }
+    }
+
+    pub fn unknown_count(&self) -> usize {
+        self.unknown.values().sum()
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<6} {:>10} {:>10} {:>10} {:>10}",
+            "Layer", "Converted", "Skipped", "Dropped", "Unknown"
+        )?;
+        for layer in &self.layers {
+            writeln!(
+                f,
+                "{:<6} {:>10} {:>10} {:>10} {:>10}",
+                layer.name,
+                layer.converted,
+                layer.skipped,
+                layer.dropped,
+                layer.unknown_count()
+            )?;
+            for (kkod, count) in &layer.unknown {
+                writeln!(f, "         unknown kkod {}: {}", kkod, count)?;
+            }
+            for (kkod, count) in &layer.unmapped {
+                writeln!(f, "         unmapped kkod {}: {}", kkod, count)?;
+            }
+        }
+        for name in &self.missing {
+            writeln!(f, "Missing shape file for layer {}", name)?;
+        }
+        Ok(())
+    }
+}
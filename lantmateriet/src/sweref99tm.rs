@@ -1,4 +1,4 @@
-//! Math to convert from sweref 99 to WGS 84 coordinate systems.
+//! Math to convert between sweref 99 and WGS 84 coordinate systems.
 //! See: https://www.lantmateriet.se/globalassets/kartor-och-geografisk-information/gps-och-geodetisk-matning/gauss_conformal_projection.pdf
 
 use std::f64::consts::PI;
@@ -19,6 +19,17 @@ const PHI2: f64 = N * N / 48.0 + N * N * N / 15.0 - 437.0 * N * N * N * N / 1440
 const PHI3: f64 = 17.0 * N * N * N / 480.0 - 37.0 * N * N * N * N / 840.0;
 const PHI4: f64 = 4397.0 * N * N * N * N / 161_280.0;
 
+const BETA1: f64 =
+    N / 2.0 - 2.0 * N * N / 3.0 + 5.0 * N * N * N / 16.0 + 41.0 * N * N * N * N / 180.0;
+const BETA2: f64 = 13.0 * N * N / 48.0 - 3.0 * N * N * N / 5.0 + 557.0 * N * N * N * N / 1440.0;
+const BETA3: f64 = 61.0 * N * N * N / 240.0 - 103.0 * N * N * N * N / 140.0;
+const BETA4: f64 = 49561.0 * N * N * N * N / 161_280.0;
+
+const A_FORWARD: f64 = E2;
+const B_FORWARD: f64 = (5.0 * E2 * E2 - E2 * E2 * E2) / 6.0;
+const C_FORWARD: f64 = (104.0 * E2 * E2 * E2 - 45.0 * E2 * E2 * E2 * E2) / 120.0;
+const D_FORWARD: f64 = (1237.0 * E2 * E2 * E2 * E2) / 1260.0;
+
 const A_STAR: f64 = E2 + E2 * E2 + E2 * E2 * E2 + E2 * E2 * E2 * E2;
 const B_STAR: f64 = -(7.0 * E2 * E2 + 17.0 * E2 * E2 * E2 + 30.0 * E2 * E2 * E2 * E2) / 6.0;
 const C_STAR: f64 = (224.0 * E2 * E2 * E2 + 889.0 * E2 * E2 * E2 * E2) / 120.0;
@@ -55,3 +66,57 @@ pub fn to_wgs(north: f64, east: f64) -> Coordinate {
     let lat = lat_radian * 180.0 / PI;
     Coordinate::new(lat, lon)
 }
+
+/// North and east in sweref 99 TM of a WGS 84 coordinate.
+pub fn from_wgs(coordinate: &Coordinate) -> (f64, f64) {
+    let phi = coordinate.lat() * DEG_TO_RAD;
+    let lambda = coordinate.lon() * DEG_TO_RAD;
+    let sin_phi = phi.sin();
+    let phi_star = phi
+        - sin_phi
+            * phi.cos()
+            * (A_FORWARD
+                + B_FORWARD * sin_phi.powi(2)
+                + C_FORWARD * sin_phi.powi(4)
+                + D_FORWARD * sin_phi.powi(6));
+    let delta_lambda = lambda - LAMBDA_ZERO;
+    let xi_prim = (phi_star.tan() / delta_lambda.cos()).atan();
+    let eta_prim = (phi_star.cos() * delta_lambda.sin()).atanh();
+
+    let north = SCALE
+        * A
+        * (xi_prim
+            + BETA1 * (2.0 * xi_prim).sin() * (2.0 * eta_prim).cosh()
+            + BETA2 * (4.0 * xi_prim).sin() * (4.0 * eta_prim).cosh()
+            + BETA3 * (6.0 * xi_prim).sin() * (6.0 * eta_prim).cosh()
+            + BETA4 * (8.0 * xi_prim).sin() * (8.0 * eta_prim).cosh())
+        + FALSE_NORTHING;
+    let east = SCALE
+        * A
+        * (eta_prim
+            + BETA1 * (2.0 * xi_prim).cos() * (2.0 * eta_prim).sinh()
+            + BETA2 * (4.0 * xi_prim).cos() * (4.0 * eta_prim).sinh()
+            + BETA3 * (6.0 * xi_prim).cos() * (6.0 * eta_prim).sinh()
+            + BETA4 * (8.0 * xi_prim).cos() * (8.0 * eta_prim).sinh())
+        + FALSE_EASTING;
+    (north, east)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for &(north, east) in &[
+            (6_160_000.0, 380_000.0),
+            (6_730_000.0, 615_000.0),
+            (7_600_000.0, 850_000.0),
+        ] {
+            // `Coordinate` keeps 100 nanodegrees, about a centimeter.
+            let (n, e) = from_wgs(&to_wgs(north, east));
+            assert!((n - north).abs() < 0.01, "north {} != {}", n, north);
+            assert!((e - east).abs() < 0.01, "east {} != {}", e, east);
+        }
+    }
+}
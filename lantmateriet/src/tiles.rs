@@ -0,0 +1,276 @@
+//! Mapbox vector tiles of the converted features, written as an MBTiles or PMTiles archive.
+//!
+//! Every lantmäteriet layer becomes a layer in the tiles named after the file, e.g. `vl`, with the
+//! tags of the features as properties. A layer is included from `min_zoom` to `max_zoom` of its
+//! file in the config, within the zoom levels of the archive. Geometries are clipped to each tile
+//! and quantized to the tile's coordinates on every zoom level.
+
+mod clip;
+mod mbtiles;
+mod mvt;
+mod pmtiles;
+
+use crate::config::Config;
+use crate::process::{Feature, Sink};
+use crate::shape::Shape;
+use crate::tiles::clip::{tile_range, Bounds, Geometry};
+use crate::tiles::mvt::TileBuilder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::io::Write;
+use std::path::Path;
+
+pub const DEFAULT_MIN_ZOOM: u8 = 0;
+pub const DEFAULT_MAX_ZOOM: u8 = 14;
+
+/// Highest zoom level tiles can be generated for.
+pub const MAX_ZOOM: u8 = 22;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileFormat {
+    MbTiles,
+    PmTiles,
+}
+
+/// Collects converted features and writes them as vector tiles.
+pub struct VectorTiles {
+    name: String,
+    min_zoom: u8,
+    max_zoom: u8,
+    layers: Vec<Layer>,
+    /// West, south, east and north in degrees.
+    bounds: Bounds,
+}
+
+struct Layer {
+    name: String,
+    min_zoom: u8,
+    max_zoom: u8,
+    features: Vec<TileFeature>,
+    /// Tag keys of the features.
+    fields: BTreeSet<String>,
+}
+
+struct TileFeature {
+    geometry: Geometry,
+    bounds: Bounds,
+    tags: Vec<(String, String)>,
+}
+
+/// A gzipped tile.
+struct Tile {
+    zoom: u8,
+    x: u32,
+    y: u32,
+    data: Vec<u8>,
+}
+
+/// What the archive formats store about the tiles.
+struct Metadata {
+    name: String,
+    min_zoom: u8,
+    max_zoom: u8,
+    bounds: Bounds,
+    /// Name, zoom levels and fields of each layer.
+    layers: Vec<(String, u8, u8, Vec<String>)>,
+}
+
+impl TileFormat {
+    /// The format of `path` by its extension, `.mbtiles` or `.pmtiles`.
+    pub fn from_path(path: &str) -> Option<TileFormat> {
+        let extension = path.rsplit_once('.')?.1;
+        Self::from_name(&extension.to_lowercase())
+    }
+
+    /// The format named `mbtiles` or `pmtiles`.
+    pub fn from_name(name: &str) -> Option<TileFormat> {
+        match name {
+            "mbtiles" => Some(TileFormat::MbTiles),
+            "pmtiles" => Some(TileFormat::PmTiles),
+            _ => None,
+        }
+    }
+}
+
+impl VectorTiles {
+    /// Tiles from `min_zoom` to `max_zoom`. The layers of the config are added in order, with the
+    /// zoom levels of their files.
+    pub fn new(name: &str, config: &Config, min_zoom: u8, max_zoom: u8) -> VectorTiles {
+        let max_zoom = max_zoom.min(MAX_ZOOM);
+        let layers = config
+            .files
+            .iter()
+            .map(|file| Layer {
+                name: file.name.clone(),
+                min_zoom: file.min_zoom.unwrap_or(min_zoom).max(min_zoom),
+                max_zoom: file.max_zoom.unwrap_or(max_zoom).min(max_zoom),
+                features: Vec::new(),
+                fields: BTreeSet::new(),
+            })
+            .collect();
+
+        VectorTiles {
+            name: name.to_owned(),
+            min_zoom,
+            max_zoom,
+            layers,
+            bounds: [f64::MAX, f64::MAX, f64::MIN, f64::MIN],
+        }
+    }
+
+    /// Generates the tiles and writes them to `path`. Returns the number of tiles.
+    pub fn write<P: AsRef<Path>>(&self, path: P, format: TileFormat) -> io::Result<usize> {
+        let mut tiles = self.tiles()?;
+        let metadata = self.metadata();
+        match format {
+            TileFormat::MbTiles => mbtiles::write(path.as_ref(), &tiles, &metadata)?,
+            TileFormat::PmTiles => pmtiles::write(path.as_ref(), &mut tiles, &metadata)?,
+        }
+        Ok(tiles.len())
+    }
+
+    fn tiles(&self) -> io::Result<Vec<Tile>> {
+        let mut tiles = Vec::new();
+        for zoom in self.min_zoom..=self.max_zoom {
+            let mut builders: BTreeMap<(u32, u32), TileBuilder> = BTreeMap::new();
+            for layer in &self.layers {
+                if zoom < layer.min_zoom || zoom > layer.max_zoom {
+                    continue;
+                }
+                for feature in &layer.features {
+                    for (x, y) in tile_range(&feature.bounds, zoom) {
+                        if let Some(geometry) = feature.geometry.clip(zoom, x, y) {
+                            builders.entry((x, y)).or_default().add(
+                                &layer.name,
+                                &geometry,
+                                &feature.tags,
+                            );
+                        }
+                    }
+                }
+            }
+
+            for ((x, y), builder) in builders {
+                tiles.push(Tile {
+                    zoom,
+                    x,
+                    y,
+                    data: gzip(&builder.encode())?,
+                });
+            }
+        }
+        Ok(tiles)
+    }
+
+    fn metadata(&self) -> Metadata {
+        let bounds = if self.bounds[0] <= self.bounds[2] {
+            self.bounds
+        } else {
+            [-180.0, -85.0, 180.0, 85.0]
+        };
+        Metadata {
+            name: self.name.clone(),
+            min_zoom: self.min_zoom,
+            max_zoom: self.max_zoom,
+            bounds,
+            layers: self
+                .layers
+                .iter()
+                .filter(|l| !l.features.is_empty())
+                .map(|l| {
+                    let fields = l.fields.iter().cloned().collect();
+                    (l.name.clone(), l.min_zoom, l.max_zoom, fields)
+                })
+                .collect(),
+        }
+    }
+
+    fn extend_bounds(&mut self, shape: &Shape) {
+        let mut extend = |lon: f64, lat: f64| {
+            let [west, south, east, north] = self.bounds;
+            self.bounds = [west.min(lon), south.min(lat), east.max(lon), north.max(lat)];
+        };
+        match shape {
+            Shape::Point(c) => extend(c.lon(), c.lat()),
+            Shape::PolyLine(poly) | Shape::Polygon(poly) => {
+                for c in poly.parts.iter().flatten() {
+                    extend(c.lon(), c.lat());
+                }
+            }
+        }
+    }
+}
+
+/// Features of layers that are not in the config get a layer with all zoom levels.
+impl Sink for VectorTiles {
+    fn add(&mut self, feature: Feature) -> io::Result<()> {
+        self.extend_bounds(&feature.shape);
+
+        let index = match self.layers.iter().position(|l| l.name == feature.layer) {
+            Some(index) => index,
+            None => {
+                self.layers.push(Layer {
+                    name: feature.layer.clone(),
+                    min_zoom: self.min_zoom,
+                    max_zoom: self.max_zoom,
+                    features: Vec::new(),
+                    fields: BTreeSet::new(),
+                });
+                self.layers.len() - 1
+            }
+        };
+
+        let layer = &mut self.layers[index];
+        let tags: Vec<(String, String)> =
+            feature.tags.into_iter().map(|t| (t.key, t.value)).collect();
+        layer.fields.extend(tags.iter().map(|(k, _)| k.clone()));
+
+        let geometry = Geometry::project(&feature.shape);
+        layer.features.push(TileFeature {
+            bounds: geometry.bounds(),
+            geometry,
+            tags,
+        });
+        Ok(())
+    }
+}
+
+impl Metadata {
+    /// The TileJSON fields of the archive, with the `vector_layers` of the tiles.
+    fn json(&self) -> Value {
+        let layers: Vec<Value> = self
+            .layers
+            .iter()
+            .map(|(name, min_zoom, max_zoom, fields)| {
+                let fields: serde_json::Map<String, Value> = fields
+                    .iter()
+                    .map(|f| (f.clone(), Value::from("String")))
+                    .collect();
+                json!({
+                    "id": name,
+                    "fields": fields,
+                    "minzoom": min_zoom,
+                    "maxzoom": max_zoom,
+                })
+            })
+            .collect();
+
+        json!({
+            "name": self.name,
+            "format": "pbf",
+            "minzoom": self.min_zoom,
+            "maxzoom": self.max_zoom,
+            "bounds": self.bounds,
+            "vector_layers": layers,
+        })
+    }
+}
+
+fn gzip(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
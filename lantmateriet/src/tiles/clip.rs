@@ -0,0 +1,363 @@
+//! Projection of shapes to web mercator, and clipping and quantization of them to tiles.
+
+use crate::shape::Shape;
+use std::f64::consts::PI;
+use vadeen_osm::geo::Coordinate;
+
+/// Size of a tile in tile coordinates.
+pub(super) const EXTENT: u32 = 4096;
+
+/// Geometries are clipped this far outside of the tile, so lines and borders continue smoothly
+/// across tile borders.
+const BUFFER: f64 = 64.0;
+
+/// Web mercator latitude limit.
+const MAX_LAT: f64 = 85.051_128_78;
+
+/// Web mercator with x and y from 0 to 1, y grows southwards.
+pub(super) type Point = (f64, f64);
+
+pub(super) type TilePoint = (i32, i32);
+
+#[derive(Debug)]
+pub(super) enum Geometry {
+    Point(Point),
+    Lines(Vec<Vec<Point>>),
+    Polygon(Vec<Ring>),
+}
+
+#[derive(Debug)]
+pub(super) struct Ring {
+    points: Vec<Point>,
+    outer: bool,
+}
+
+/// A geometry in the coordinates of one tile. Polygon rings are oriented as the vector tile spec
+/// wants, each outer ring is followed by its holes.
+#[derive(Debug)]
+pub(super) enum TileGeometry {
+    Point(TilePoint),
+    Lines(Vec<Vec<TilePoint>>),
+    Polygon(Vec<Vec<TilePoint>>),
+}
+
+/// Min x, min y, max x and max y.
+pub(super) type Bounds = [f64; 4];
+
+impl Geometry {
+    pub(super) fn project(shape: &Shape) -> Geometry {
+        let project_all =
+            |part: &Vec<Coordinate>| -> Vec<Point> { part.iter().map(project).collect() };
+        match shape {
+            Shape::Point(c) => Geometry::Point(project(c)),
+            Shape::PolyLine(poly) => Geometry::Lines(poly.parts.iter().map(project_all).collect()),
+            Shape::Polygon(poly) => {
+                let rings: Vec<Vec<Point>> = poly.parts.iter().map(project_all).collect();
+                Geometry::Polygon(classify(rings))
+            }
+        }
+    }
+
+    pub(super) fn bounds(&self) -> Bounds {
+        let points: Box<dyn Iterator<Item = &Point>> = match self {
+            Geometry::Point(p) => Box::new(std::iter::once(p)),
+            Geometry::Lines(parts) => Box::new(parts.iter().flatten()),
+            Geometry::Polygon(rings) => Box::new(rings.iter().flat_map(|r| &r.points)),
+        };
+        points.fold(
+            [f64::MAX, f64::MAX, f64::MIN, f64::MIN],
+            |[min_x, min_y, max_x, max_y], &(x, y)| {
+                [min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y)]
+            },
+        )
+    }
+
+    /// The geometry in tile `x`, `y` of `zoom`, or `None` if nothing of it is in the tile.
+    pub(super) fn clip(&self, zoom: u8, x: u32, y: u32) -> Option<TileGeometry> {
+        let scale = f64::from(1u32 << zoom);
+        let to_tile = |&(px, py): &Point| -> Point {
+            (
+                (px * scale - f64::from(x)) * f64::from(EXTENT),
+                (py * scale - f64::from(y)) * f64::from(EXTENT),
+            )
+        };
+        let min = -BUFFER;
+        let max = f64::from(EXTENT) + BUFFER;
+
+        match self {
+            Geometry::Point(p) => {
+                let (px, py) = to_tile(p);
+                let extent = f64::from(EXTENT);
+                if px >= 0.0 && px < extent && py >= 0.0 && py < extent {
+                    Some(TileGeometry::Point((px.round() as i32, py.round() as i32)))
+                } else {
+                    None
+                }
+            }
+            Geometry::Lines(parts) => {
+                let lines: Vec<Vec<TilePoint>> = parts
+                    .iter()
+                    .flat_map(|part| {
+                        let part: Vec<Point> = part.iter().map(to_tile).collect();
+                        clip_line(&part, min, max)
+                    })
+                    .map(|line| quantize(&line))
+                    .filter(|line| line.len() >= 2)
+                    .collect();
+                if lines.is_empty() {
+                    None
+                } else {
+                    Some(TileGeometry::Lines(lines))
+                }
+            }
+            Geometry::Polygon(rings) => {
+                let mut out: Vec<Vec<TilePoint>> = Vec::new();
+                let mut has_outer = false;
+                for ring in rings {
+                    // Holes of outer rings that are outside of the tile are dropped with them.
+                    if !ring.outer && !has_outer {
+                        continue;
+                    }
+                    let points: Vec<Point> = ring.points.iter().map(to_tile).collect();
+                    let mut points = quantize(&clip_ring(&points, min, max));
+                    if points.first() == points.last() {
+                        points.pop();
+                    }
+
+                    let ring_area = area(&points);
+                    if points.len() < 3 || ring_area == 0 {
+                        if ring.outer {
+                            has_outer = false;
+                        }
+                        continue;
+                    }
+                    if (ring_area > 0) != ring.outer {
+                        points.reverse();
+                    }
+                    has_outer |= ring.outer;
+                    out.push(points);
+                }
+                if out.is_empty() {
+                    None
+                } else {
+                    Some(TileGeometry::Polygon(out))
+                }
+            }
+        }
+    }
+}
+
+/// Tiles of `zoom` that `bounds` touch, including the buffer.
+pub(super) fn tile_range(bounds: &Bounds, zoom: u8) -> impl Iterator<Item = (u32, u32)> {
+    let tiles = f64::from(1u32 << zoom);
+    let last = (1u32 << zoom) - 1;
+    let buffer = BUFFER / f64::from(EXTENT) / tiles;
+    let tile = |v: f64| ((v * tiles).floor().max(0.0) as u32).min(last);
+
+    let (min_x, min_y) = (tile(bounds[0] - buffer), tile(bounds[1] - buffer));
+    let (max_x, max_y) = (tile(bounds[2] + buffer), tile(bounds[3] + buffer));
+    (min_x..=max_x).flat_map(move |x| (min_y..=max_y).map(move |y| (x, y)))
+}
+
+fn project(c: &Coordinate) -> Point {
+    let lat = c.lat().clamp(-MAX_LAT, MAX_LAT).to_radians();
+    let x = (c.lon() + 180.0) / 360.0;
+    let y = 0.5 - (PI / 4.0 + lat / 2.0).tan().ln() / (2.0 * PI);
+    (x, y)
+}
+
+/// Marks the rings as outer rings or holes. Shape files have clockwise outer rings, which is a
+/// positive area with y growing southwards. If no ring is clockwise the orientation is taken to
+/// be reversed.
+fn classify(rings: Vec<Vec<Point>>) -> Vec<Ring> {
+    let areas: Vec<f64> = rings.iter().map(Vec::as_slice).map(area_f64).collect();
+    let reversed = !areas.iter().any(|a| *a > 0.0);
+    rings
+        .into_iter()
+        .zip(areas)
+        .map(|(points, area)| Ring {
+            points,
+            outer: (area > 0.0) != reversed,
+        })
+        .collect()
+}
+
+/// Splits a line into the parts inside the box from `min` to `max`.
+fn clip_line(points: &[Point], min: f64, max: f64) -> Vec<Vec<Point>> {
+    let mut lines = Vec::new();
+    let mut current: Vec<Point> = Vec::new();
+    for segment in points.windows(2) {
+        match clip_segment(segment[0], segment[1], min, max) {
+            Some((a, b)) => {
+                if current.is_empty() {
+                    current.push(a);
+                }
+                current.push(b);
+                if b != segment[1] {
+                    lines.push(std::mem::take(&mut current));
+                }
+            }
+            None => {
+                if !current.is_empty() {
+                    lines.push(std::mem::take(&mut current));
+                }
+            }
+        }
+    }
+    if current.len() >= 2 {
+        lines.push(current);
+    }
+    lines
+}
+
+/// The part of the segment inside the box, Liang-Barsky. Ends inside the box are returned as is.
+fn clip_segment(a: Point, b: Point, min: f64, max: f64) -> Option<(Point, Point)> {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let mut t0 = 0.0;
+    let mut t1 = 1.0;
+    for &(p, q) in &[
+        (-dx, a.0 - min),
+        (dx, max - a.0),
+        (-dy, a.1 - min),
+        (dy, max - a.1),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+            continue;
+        }
+        let r = q / p;
+        if p < 0.0 {
+            if r > t1 {
+                return None;
+            }
+            t0 = f64::max(t0, r);
+        } else {
+            if r < t0 {
+                return None;
+            }
+            t1 = f64::min(t1, r);
+        }
+    }
+
+    let at = |t: f64| (a.0 + t * dx, a.1 + t * dy);
+    let start = if t0 > 0.0 { at(t0) } else { a };
+    let end = if t1 < 1.0 { at(t1) } else { b };
+    Some((start, end))
+}
+
+/// Clips a ring to the box from `min` to `max`, Sutherland-Hodgman. Parts of the ring outside
+/// the box are replaced by the box border.
+fn clip_ring(ring: &[Point], min: f64, max: f64) -> Vec<Point> {
+    let mut out = ring.to_vec();
+    for edge in 0..4 {
+        let input = std::mem::take(&mut out);
+        let last = match input.last() {
+            Some(last) => *last,
+            None => break,
+        };
+
+        let inside = |p: Point| match edge {
+            0 => p.0 >= min,
+            1 => p.0 <= max,
+            2 => p.1 >= min,
+            _ => p.1 <= max,
+        };
+        let intersection = |a: Point, b: Point| {
+            let border = if edge % 2 == 0 { min } else { max };
+            if edge < 2 {
+                let t = (border - a.0) / (b.0 - a.0);
+                (border, a.1 + t * (b.1 - a.1))
+            } else {
+                let t = (border - a.1) / (b.1 - a.1);
+                (a.0 + t * (b.0 - a.0), border)
+            }
+        };
+
+        let mut previous = last;
+        for &point in &input {
+            if inside(point) {
+                if !inside(previous) {
+                    out.push(intersection(previous, point));
+                }
+                out.push(point);
+            } else if inside(previous) {
+                out.push(intersection(previous, point));
+            }
+            previous = point;
+        }
+    }
+    out
+}
+
+/// Rounds to tile coordinates and removes repeated points.
+fn quantize(points: &[Point]) -> Vec<TilePoint> {
+    let mut out: Vec<TilePoint> = Vec::with_capacity(points.len());
+    for &(x, y) in points {
+        let point = (x.round() as i32, y.round() as i32);
+        if out.last() != Some(&point) {
+            out.push(point);
+        }
+    }
+    out
+}
+
+/// Twice the signed area of a ring, positive if clockwise with y growing downwards.
+fn area(ring: &[TilePoint]) -> i64 {
+    let mut sum = 0;
+    for (i, a) in ring.iter().enumerate() {
+        let b = ring[(i + 1) % ring.len()];
+        sum += i64::from(a.0) * i64::from(b.1) - i64::from(b.0) * i64::from(a.1);
+    }
+    sum
+}
+
+fn area_f64(ring: &[Point]) -> f64 {
+    let mut sum = 0.0;
+    for (i, a) in ring.iter().enumerate() {
+        let b = ring[(i + 1) % ring.len()];
+        sum += a.0 * b.1 - b.0 * a.1;
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape::Poly;
+
+    fn ring(points: &[(f64, f64)]) -> Vec<Coordinate> {
+        points
+            .iter()
+            .map(|&(lat, lon)| Coordinate::new(lat, lon))
+            .collect()
+    }
+
+    #[test]
+    fn rings_keep_their_winding_after_clipping() {
+        // A clockwise outer ring across the tile border and a counter-clockwise hole inside the tile.
+        let outer = ring(&[
+            (10.0, -10.0),
+            (10.0, 10.0),
+            (-10.0, 10.0),
+            (-10.0, -10.0),
+            (10.0, -10.0),
+        ]);
+        let hole = ring(&[(5.0, 2.0), (2.0, 2.0), (2.0, 5.0), (5.0, 5.0), (5.0, 2.0)]);
+        let shape = Shape::Polygon(Poly::new(vec![outer, hole]));
+
+        // The north east quarter of the world.
+        let rings = match Geometry::project(&shape).clip(1, 1, 0) {
+            Some(TileGeometry::Polygon(rings)) => rings,
+            other => panic!("Expected a polygon, got {:?}", other),
+        };
+        assert_eq!(rings.len(), 2);
+        assert!(area(&rings[0]) > 0);
+        assert!(area(&rings[1]) < 0);
+
+        let (min, max) = (-BUFFER as i32, (f64::from(EXTENT) + BUFFER) as i32);
+        let inside = |&(x, y): &TilePoint| x >= min && x <= max && y >= min && y <= max;
+        assert!(rings.iter().flatten().all(inside));
+    }
+}
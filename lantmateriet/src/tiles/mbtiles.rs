@@ -0,0 +1,72 @@
+//! MBTiles archive, a SQLite database with the tiles.
+//! See: https://github.com/mapbox/mbtiles-spec/blob/master/1.3/spec.md
+
+use crate::tiles::{Metadata, Tile};
+use rusqlite::{params, Connection};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const SCHEMA: &str = "
+    CREATE TABLE metadata (name TEXT, value TEXT);
+    CREATE TABLE tiles (zoom_level INTEGER, tile_column INTEGER, tile_row INTEGER, tile_data BLOB);
+    CREATE UNIQUE INDEX tile_index ON tiles (zoom_level, tile_column, tile_row);
+";
+
+/// Writes the tiles to a new database at `path`, an existing file is replaced.
+pub(super) fn write(path: &Path, tiles: &[Tile], metadata: &Metadata) -> io::Result<()> {
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+
+    let mut connection = Connection::open(path).map_err(sql_error)?;
+    connection.execute_batch(SCHEMA).map_err(sql_error)?;
+
+    let transaction = connection.transaction().map_err(sql_error)?;
+    {
+        let [west, south, east, north] = metadata.bounds;
+        let mut statement = transaction
+            .prepare("INSERT INTO metadata (name, value) VALUES (?1, ?2)")
+            .map_err(sql_error)?;
+        let values = [
+            ("name", metadata.name.clone()),
+            ("format", "pbf".to_owned()),
+            ("type", "overlay".to_owned()),
+            ("minzoom", metadata.min_zoom.to_string()),
+            ("maxzoom", metadata.max_zoom.to_string()),
+            ("bounds", format!("{},{},{},{}", west, south, east, north)),
+            (
+                "center",
+                format!(
+                    "{},{},{}",
+                    (west + east) / 2.0,
+                    (south + north) / 2.0,
+                    metadata.min_zoom
+                ),
+            ),
+            ("json", metadata.json().to_string()),
+        ];
+        for (name, value) in &values {
+            statement.execute(params![name, value]).map_err(sql_error)?;
+        }
+
+        let mut statement = transaction
+            .prepare(
+                "INSERT INTO tiles (zoom_level, tile_column, tile_row, tile_data)
+                 VALUES (?1, ?2, ?3, ?4)",
+            )
+            .map_err(sql_error)?;
+        for tile in tiles {
+            // Rows are numbered from the south.
+            let row = (1u32 << tile.zoom) - 1 - tile.y;
+            statement
+                .execute(params![tile.zoom, tile.x, row, tile.data])
+                .map_err(sql_error)?;
+        }
+    }
+    transaction.commit().map_err(sql_error)
+}
+
+fn sql_error(e: rusqlite::Error) -> io::Error {
+    io::Error::other(e.to_string())
+}
@@ -0,0 +1,221 @@
+//! Protobuf encoding of a vector tile.
+//! See: https://github.com/mapbox/vector-tile-spec/tree/master/2.1
+
+use crate::tiles::clip::{TileGeometry, TilePoint, EXTENT};
+use std::collections::HashMap;
+
+const MOVE_TO: u32 = 1;
+const LINE_TO: u32 = 2;
+const CLOSE_PATH: u32 = 7;
+
+const POINT: u64 = 1;
+const LINESTRING: u64 = 2;
+const POLYGON: u64 = 3;
+
+/// Protobuf wire types.
+const VARINT: u32 = 0;
+const LENGTH_DELIMITED: u32 = 2;
+
+/// A tile being built, layers are written in the order they are added.
+#[derive(Default)]
+pub(super) struct TileBuilder {
+    layers: Vec<LayerBuilder>,
+}
+
+struct LayerBuilder {
+    name: String,
+    keys: Vec<String>,
+    key_index: HashMap<String, u32>,
+    values: Vec<String>,
+    value_index: HashMap<String, u32>,
+    /// Encoded feature messages.
+    features: Vec<Vec<u8>>,
+}
+
+impl TileBuilder {
+    pub(super) fn add(&mut self, layer: &str, geometry: &TileGeometry, tags: &[(String, String)]) {
+        let index = match self.layers.iter().position(|l| l.name == layer) {
+            Some(index) => index,
+            None => {
+                self.layers.push(LayerBuilder::new(layer));
+                self.layers.len() - 1
+            }
+        };
+        self.layers[index].add(geometry, tags);
+    }
+
+    pub(super) fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for layer in &self.layers {
+            bytes_field(&mut out, 3, &layer.encode());
+        }
+        out
+    }
+}
+
+impl LayerBuilder {
+    fn new(name: &str) -> LayerBuilder {
+        LayerBuilder {
+            name: name.to_owned(),
+            keys: Vec::new(),
+            key_index: HashMap::new(),
+            values: Vec::new(),
+            value_index: HashMap::new(),
+            features: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, geometry: &TileGeometry, tags: &[(String, String)]) {
+        let mut tag_indices = Vec::with_capacity(tags.len() * 2);
+        for (key, value) in tags {
+            tag_indices.push(index(&mut self.keys, &mut self.key_index, key));
+            tag_indices.push(index(&mut self.values, &mut self.value_index, value));
+        }
+
+        let (geometry_type, commands) = commands(geometry);
+        let mut feature = Vec::new();
+        packed_field(&mut feature, 2, &tag_indices);
+        field(&mut feature, 3, VARINT);
+        varint(&mut feature, geometry_type);
+        packed_field(&mut feature, 4, &commands);
+        self.features.push(feature);
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        field(&mut out, 15, VARINT);
+        varint(&mut out, 2);
+        bytes_field(&mut out, 1, self.name.as_bytes());
+        for feature in &self.features {
+            bytes_field(&mut out, 2, feature);
+        }
+        for key in &self.keys {
+            bytes_field(&mut out, 3, key.as_bytes());
+        }
+        for value in &self.values {
+            // All values are strings, field 1 of the value message.
+            let mut message = Vec::new();
+            bytes_field(&mut message, 1, value.as_bytes());
+            bytes_field(&mut out, 4, &message);
+        }
+        field(&mut out, 5, VARINT);
+        varint(&mut out, u64::from(EXTENT));
+        out
+    }
+}
+
+/// Index of `value` in `values`, added if it's not there.
+fn index(values: &mut Vec<String>, indices: &mut HashMap<String, u32>, value: &str) -> u32 {
+    if let Some(index) = indices.get(value) {
+        return *index;
+    }
+    let index = values.len() as u32;
+    values.push(value.to_owned());
+    indices.insert(value.to_owned(), index);
+    index
+}
+
+/// The geometry type and commands of a geometry. Coordinates are relative to the previous point.
+fn commands(geometry: &TileGeometry) -> (u64, Vec<u32>) {
+    let mut commands = Vec::new();
+    let mut cursor = (0, 0);
+    let geometry_type = match geometry {
+        TileGeometry::Point(point) => {
+            commands.push(command(MOVE_TO, 1));
+            move_cursor(&mut commands, &mut cursor, *point);
+            POINT
+        }
+        TileGeometry::Lines(lines) => {
+            for line in lines {
+                path(&mut commands, &mut cursor, line);
+            }
+            LINESTRING
+        }
+        TileGeometry::Polygon(rings) => {
+            for ring in rings {
+                path(&mut commands, &mut cursor, ring);
+                commands.push(command(CLOSE_PATH, 1));
+            }
+            POLYGON
+        }
+    };
+    (geometry_type, commands)
+}
+
+fn path(commands: &mut Vec<u32>, cursor: &mut TilePoint, points: &[TilePoint]) {
+    commands.push(command(MOVE_TO, 1));
+    move_cursor(commands, cursor, points[0]);
+    commands.push(command(LINE_TO, points.len() - 1));
+    for point in &points[1..] {
+        move_cursor(commands, cursor, *point);
+    }
+}
+
+fn move_cursor(commands: &mut Vec<u32>, cursor: &mut TilePoint, point: TilePoint) {
+    commands.push(zigzag(point.0 - cursor.0));
+    commands.push(zigzag(point.1 - cursor.1));
+    *cursor = point;
+}
+
+fn command(id: u32, count: usize) -> u32 {
+    id | ((count as u32) << 3)
+}
+
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+fn field(out: &mut Vec<u8>, number: u32, wire_type: u32) {
+    varint(out, u64::from((number << 3) | wire_type));
+}
+
+fn bytes_field(out: &mut Vec<u8>, number: u32, data: &[u8]) {
+    field(out, number, LENGTH_DELIMITED);
+    varint(out, data.len() as u64);
+    out.extend_from_slice(data);
+}
+
+fn packed_field(out: &mut Vec<u8>, number: u32, values: &[u32]) {
+    if values.is_empty() {
+        return;
+    }
+    let mut data = Vec::with_capacity(values.len());
+    for value in values {
+        varint(&mut data, u64::from(*value));
+    }
+    bytes_field(out, number, &data);
+}
+
+pub(super) fn varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_commands_relative_to_the_cursor() {
+        // The point example of the vector tile spec.
+        let (geometry_type, point) = commands(&TileGeometry::Point((25, 17)));
+        assert_eq!(geometry_type, POINT);
+        assert_eq!(point, vec![9, 50, 34]);
+
+        let ring = vec![(3, 6), (8, 12), (20, 34)];
+        let (geometry_type, polygon) = commands(&TileGeometry::Polygon(vec![ring]));
+        assert_eq!(geometry_type, POLYGON);
+        assert_eq!(polygon, vec![9, 6, 12, 18, 10, 12, 24, 44, 15]);
+    }
+
+    #[test]
+    fn zigzag_encodes_negative_values_as_odd() {
+        assert_eq!(zigzag(0), 0);
+        assert_eq!(zigzag(-1), 1);
+        assert_eq!(zigzag(1), 2);
+        assert_eq!(zigzag(-2), 3);
+    }
+}
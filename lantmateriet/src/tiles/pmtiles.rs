@@ -0,0 +1,206 @@
+//! PMTiles version 3 archive, a single file that can be served from static hosting.
+//! See: https://github.com/protomaps/PMTiles/blob/main/spec/v3/spec.md
+
+use crate::tiles::mvt::varint;
+use crate::tiles::{gzip, Metadata, Tile};
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const HEADER_SIZE: usize = 127;
+
+/// The header and root directory must fit in the first 16 KiB.
+const MAX_ROOT_SIZE: usize = 16_384 - HEADER_SIZE;
+
+const COMPRESSION_GZIP: u8 = 2;
+const TILE_TYPE_MVT: u8 = 1;
+
+struct Entry {
+    tile_id: u64,
+    offset: u64,
+    length: u64,
+    /// Number of consecutive tiles with the same data, 0 for a leaf directory.
+    run_length: u64,
+}
+
+pub(super) fn write(path: &Path, tiles: &mut [Tile], metadata: &Metadata) -> io::Result<()> {
+    tiles.sort_by_key(|t| tile_id(t.zoom, t.x, t.y));
+
+    // Tiles are written in tile id order, runs of equal tiles like open water are stored once.
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut data: Vec<&[u8]> = Vec::new();
+    let mut offset = 0;
+    for tile in tiles.iter() {
+        let id = tile_id(tile.zoom, tile.x, tile.y);
+        if let Some(last) = entries.last_mut() {
+            if last.tile_id + last.run_length == id && data.last() == Some(&&tile.data[..]) {
+                last.run_length += 1;
+                continue;
+            }
+        }
+        entries.push(Entry {
+            tile_id: id,
+            offset,
+            length: tile.data.len() as u64,
+            run_length: 1,
+        });
+        offset += tile.data.len() as u64;
+        data.push(&tile.data);
+    }
+
+    let (root, leaves) = directories(&entries)?;
+    let json = gzip(metadata.json().to_string().as_bytes())?;
+
+    let root_offset = HEADER_SIZE as u64;
+    let metadata_offset = root_offset + root.len() as u64;
+    let leaves_offset = metadata_offset + json.len() as u64;
+    let data_offset = leaves_offset + leaves.len() as u64;
+
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    header.extend_from_slice(b"PMTiles");
+    header.push(3);
+    for value in &[
+        root_offset,
+        root.len() as u64,
+        metadata_offset,
+        json.len() as u64,
+        leaves_offset,
+        leaves.len() as u64,
+        data_offset,
+        offset,
+        entries.iter().map(|e| e.run_length).sum(),
+        entries.len() as u64,
+        entries.len() as u64,
+    ] {
+        header.extend_from_slice(&value.to_le_bytes());
+    }
+    // Clustered, internal and tile compression, tile type and zoom levels.
+    header.extend_from_slice(&[
+        1,
+        COMPRESSION_GZIP,
+        COMPRESSION_GZIP,
+        TILE_TYPE_MVT,
+        metadata.min_zoom,
+        metadata.max_zoom,
+    ]);
+    let [west, south, east, north] = metadata.bounds;
+    for degrees in &[west, south, east, north] {
+        header.extend_from_slice(&e7(*degrees).to_le_bytes());
+    }
+    header.push(metadata.min_zoom);
+    header.extend_from_slice(&e7((west + east) / 2.0).to_le_bytes());
+    header.extend_from_slice(&e7((south + north) / 2.0).to_le_bytes());
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(&header)?;
+    writer.write_all(&root)?;
+    writer.write_all(&json)?;
+    writer.write_all(&leaves)?;
+    for tile in data {
+        writer.write_all(tile)?;
+    }
+    writer.flush()
+}
+
+/// The compressed root directory and leaf directories. Leaf directories are only used if all
+/// entries don't fit in the root directory.
+fn directories(entries: &[Entry]) -> io::Result<(Vec<u8>, Vec<u8>)> {
+    let root = gzip(&directory(entries))?;
+    if root.len() <= MAX_ROOT_SIZE {
+        return Ok((root, Vec::new()));
+    }
+
+    let mut leaf_size = 4096;
+    loop {
+        let mut leaves = Vec::new();
+        let mut root_entries = Vec::new();
+        for chunk in entries.chunks(leaf_size) {
+            let leaf = gzip(&directory(chunk))?;
+            root_entries.push(Entry {
+                tile_id: chunk[0].tile_id,
+                offset: leaves.len() as u64,
+                length: leaf.len() as u64,
+                run_length: 0,
+            });
+            leaves.extend(leaf);
+        }
+
+        let root = gzip(&directory(&root_entries))?;
+        if root.len() <= MAX_ROOT_SIZE {
+            return Ok((root, leaves));
+        }
+        leaf_size *= 2;
+    }
+}
+
+/// Entries as columns of varints, ids as deltas and offsets as 0 when following the previous.
+fn directory(entries: &[Entry]) -> Vec<u8> {
+    let mut out = Vec::new();
+    varint(&mut out, entries.len() as u64);
+
+    let mut last_id = 0;
+    for entry in entries {
+        varint(&mut out, entry.tile_id - last_id);
+        last_id = entry.tile_id;
+    }
+    for entry in entries {
+        varint(&mut out, entry.run_length);
+    }
+    for entry in entries {
+        varint(&mut out, entry.length);
+    }
+    for (i, entry) in entries.iter().enumerate() {
+        if i > 0 && entry.offset == entries[i - 1].offset + entries[i - 1].length {
+            varint(&mut out, 0);
+        } else {
+            varint(&mut out, entry.offset + 1);
+        }
+    }
+    out
+}
+
+/// Position of a tile on the hilbert curve, after all tiles of lower zoom levels.
+fn tile_id(zoom: u8, x: u32, y: u32) -> u64 {
+    let lower: u64 = (0..zoom).map(|z| 1u64 << (2 * z)).sum();
+
+    let n = 1u64 << zoom;
+    let (mut x, mut y) = (u64::from(x), u64::from(y));
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = u64::from((x & s) > 0);
+        let ry = u64::from((y & s) > 0);
+        d += s * s * ((3 * rx) ^ ry);
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    lower + d
+}
+
+fn e7(degrees: f64) -> i32 {
+    (degrees * 10_000_000.0).round() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tile_ids_follow_the_hilbert_curve() {
+        assert_eq!(tile_id(0, 0, 0), 0);
+        assert_eq!(tile_id(1, 0, 0), 1);
+        assert_eq!(tile_id(1, 0, 1), 2);
+        assert_eq!(tile_id(1, 1, 1), 3);
+        assert_eq!(tile_id(1, 1, 0), 4);
+        assert_eq!(tile_id(2, 0, 0), 5);
+        // From the tests of the PMTiles reference implementation.
+        assert_eq!(tile_id(12, 3423, 1763), 19_078_479);
+    }
+}
@@ -0,0 +1,230 @@
+//! Look of the garmin types in the TYP file, described in `styles/types.yml` or the file set with
+//! `garmin_types` in the config.
+//!
+//! Only types used by a kkod in the config are written. Types without labels get the titles of
+//! their kkods.
+
+use crate::config::{Config, GarminKind, Kkod};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::io::ErrorKind;
+
+pub const DEFAULT_TYPES: &str = include_str!("../../styles/types.yml");
+
+/// Language codes of TYP labels.
+const LANGUAGES: &[(&str, &str)] = &[
+    ("fr", "0x01"),
+    ("de", "0x02"),
+    ("nl", "0x03"),
+    ("en", "0x04"),
+    ("it", "0x05"),
+    ("fi", "0x06"),
+    ("sv", "0x07"),
+    ("es", "0x08"),
+    ("da", "0x0e"),
+    ("no", "0x0f"),
+];
+
+#[derive(Debug, Deserialize)]
+pub struct Types {
+    pub types: Vec<TypeStyle>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TypeStyle {
+    pub kind: GarminKind,
+    #[serde(rename = "type")]
+    pub garmin_type: String,
+    /// Label per language code.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    pub line_width: Option<u8>,
+    pub border_width: Option<u8>,
+    /// `#RRGGBB`, used if there is no bitmap.
+    pub color: Option<String>,
+    pub border_color: Option<String>,
+    /// Character and colour, `none` is transparent.
+    #[serde(default)]
+    pub palette: Vec<(String, String)>,
+    #[serde(default)]
+    pub bitmap: Vec<String>,
+}
+
+/// A garmin type used by the config, with its style if there is one.
+pub struct UsedType<'a> {
+    pub kind: GarminKind,
+    pub garmin_type: &'a str,
+    pub style: Option<&'a TypeStyle>,
+    pub kkods: Vec<&'a Kkod>,
+}
+
+impl Types {
+    pub fn parse(data: &str) -> io::Result<Types> {
+        serde_yaml::from_str(data)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e.to_string()))
+    }
+
+    /// The types in the config's `garmin_types`, or the default types.
+    pub fn for_config(config: &Config) -> io::Result<Types> {
+        match &config.garmin_types {
+            Some(path) => Self::parse(&fs::read_to_string(path)?),
+            None => Self::parse(DEFAULT_TYPES),
+        }
+    }
+
+    pub fn get(&self, kind: GarminKind, garmin_type: &str) -> Option<&TypeStyle> {
+        self.types
+            .iter()
+            .find(|t| t.kind == kind && type_order(&t.garmin_type) == type_order(garmin_type))
+    }
+
+    /// All types used by kkods in the config, sorted by kind and type.
+    pub fn used<'a>(&'a self, config: &'a Config) -> Vec<UsedType<'a>> {
+        let mut used: Vec<UsedType> = Vec::new();
+        for file in &config.files {
+            let mut kkods: Vec<_> = file.kkods.iter().collect();
+            kkods.sort_by_key(|(kkod, _)| (kkod.parse().unwrap_or(u32::MAX), kkod.to_string()));
+
+            for (_, kkod) in kkods {
+                let garmin = match &kkod.garmin {
+                    Some(garmin) => garmin,
+                    None => continue,
+                };
+                let index = used.iter().position(|u| {
+                    u.kind == garmin.kind
+                        && type_order(u.garmin_type) == type_order(&garmin.garmin_type)
+                });
+                match index {
+                    Some(index) => used[index].kkods.push(kkod),
+                    None => used.push(UsedType {
+                        kind: garmin.kind,
+                        garmin_type: &garmin.garmin_type,
+                        style: self.get(garmin.kind, &garmin.garmin_type),
+                        kkods: vec![kkod],
+                    }),
+                }
+            }
+        }
+        used.sort_by_key(|u| (u.kind, type_order(u.garmin_type)));
+        used
+    }
+}
+
+impl UsedType<'_> {
+    /// Labels by language code, from the style or else from the title of the first kkod.
+    pub fn labels(&self) -> Vec<(&str, &str)> {
+        if let Some(style) = self.style {
+            if !style.labels.is_empty() {
+                return style
+                    .labels
+                    .iter()
+                    .map(|(l, t)| (l.as_str(), t.as_str()))
+                    .collect();
+            }
+        }
+
+        let kkod = match self.kkods.iter().find(|k| k.title.is_some()) {
+            Some(kkod) => kkod,
+            None => return Vec::new(),
+        };
+        let mut translations: Vec<(&str, &str)> = kkod
+            .translations
+            .iter()
+            .map(|(l, t)| (l.as_str(), t.as_str()))
+            .collect();
+        translations.sort();
+
+        let mut labels = vec![("sv", kkod.title.as_deref().unwrap_or(""))];
+        labels.extend(translations);
+        labels
+    }
+
+    /// The label in `language`, or the first label.
+    pub fn label(&self, language: Option<&str>) -> Option<&str> {
+        let labels = self.labels();
+        language
+            .and_then(|l| labels.iter().find(|(code, _)| *code == l))
+            .or_else(|| labels.first())
+            .map(|(_, label)| *label)
+    }
+}
+
+/// The `[_point]`, `[_line]` and `[_polygon]` sections of all used types that have a style.
+pub fn sections(config: &Config, types: &Types) -> String {
+    let mut out = String::new();
+    for used in types.used(config) {
+        let style = match used.style {
+            Some(style) => style,
+            None => continue,
+        };
+
+        let section = match used.kind {
+            GarminKind::Point => "_point",
+            GarminKind::Line => "_line",
+            GarminKind::Polygon => "_polygon",
+        };
+        writeln!(out, "[{}]", section).unwrap();
+        writeln!(out, "Type={}", used.garmin_type).unwrap();
+        for (language, label) in used.labels() {
+            if let Some((_, code)) = LANGUAGES.iter().find(|(l, _)| *l == language) {
+                writeln!(out, "String={},\"{}\"", code, label.replace('"', "'")).unwrap();
+            }
+        }
+        if let Some(width) = style.line_width {
+            writeln!(out, "LineWidth={}", width).unwrap();
+        }
+        if let Some(width) = style.border_width {
+            writeln!(out, "BorderWidth={}", width).unwrap();
+        }
+        xpm(&mut out, used.kind, style);
+        out.push_str("[end]\n\n");
+    }
+    out
+}
+
+fn xpm(out: &mut String, kind: GarminKind, style: &TypeStyle) {
+    let name = if kind == GarminKind::Point {
+        "DayXpm"
+    } else {
+        "Xpm"
+    };
+
+    if style.bitmap.is_empty() {
+        let colors: Vec<&String> = style.color.iter().chain(&style.border_color).collect();
+        writeln!(out, "{}=\"0 0 {} 1\"", name, colors.len()).unwrap();
+        for (color, symbol) in colors.iter().zip(&["a", "b"]) {
+            writeln!(out, "\"{} c {}\"", symbol, color).unwrap();
+        }
+        return;
+    }
+
+    let width = style
+        .bitmap
+        .iter()
+        .map(|r| r.chars().count())
+        .max()
+        .unwrap_or(0);
+    writeln!(
+        out,
+        "{}=\"{} {} {} 1\"",
+        name,
+        width,
+        style.bitmap.len(),
+        style.palette.len()
+    )
+    .unwrap();
+    for (symbol, color) in &style.palette {
+        writeln!(out, "\"{} c {}\"", symbol, color).unwrap();
+    }
+    for row in &style.bitmap {
+        writeln!(out, "\"{}\"", row).unwrap();
+    }
+}
+
+/// Numeric value of a type like `0x1b`, so `0x0100` and `0x100` are the same type.
+pub(crate) fn type_order(garmin_type: &str) -> u32 {
+    u32::from_str_radix(garmin_type.trim_start_matches("0x"), 16).unwrap_or(u32::MAX)
+}
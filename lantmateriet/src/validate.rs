@@ -0,0 +1,122 @@
+//! Checks a lantmäteriet folder against the config.
+
+use crate::Lantmateriet;
+use crate::{coastline, peak};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::io::ErrorKind;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug)]
+pub struct Problem {
+    pub severity: Severity,
+    pub message: String,
+    /// Position in the config file, if the problem is in the config.
+    pub position: Option<Position>,
+}
+
+/// Line and column, both starting at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Problem {
+    fn warning(message: String) -> Problem {
+        Problem {
+            severity: Severity::Warning,
+            message,
+            position: None,
+        }
+    }
+
+    fn error(message: String) -> Problem {
+        Problem {
+            severity: Severity::Error,
+            message,
+            position: None,
+        }
+    }
+
+    pub(crate) fn warning_at(position: Position, message: String) -> Problem {
+        Problem {
+            position: Some(position),
+            ..Problem::warning(message)
+        }
+    }
+
+    pub(crate) fn error_at(position: Position, message: String) -> Problem {
+        Problem {
+            position: Some(position),
+            ..Problem::error(message)
+        }
+    }
+}
+
+/// Reads every layer in the config and reports missing files, records without kkod and kkods
+/// that are not in the config.
+pub fn validate(lantmateriet: &Lantmateriet) -> io::Result<Vec<Problem>> {
+    let mut problems = Vec::new();
+    for file_def in &lantmateriet.config().files {
+        // Generated layers, see `peak` and `coastline`.
+        if file_def.name == peak::LAYER || file_def.name == coastline::LAYER {
+            continue;
+        }
+        let shape = match lantmateriet.open_shape_file(file_def) {
+            Ok(shape) => shape,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => {
+                problems.push(Problem::warning(format!(
+                    "{}: shape file not found",
+                    file_def.name
+                )));
+                continue;
+            }
+            Err(e) => {
+                problems.push(Problem::error(format!("{}: {}", file_def.name, e)));
+                continue;
+            }
+        };
+
+        let mut unknown: BTreeMap<String, usize> = BTreeMap::new();
+        for (index, record) in shape.enumerate() {
+            match record.attributes.get("KKOD") {
+                Some(kkod) if !file_def.kkods.contains_key(kkod) => {
+                    *unknown.entry(kkod.to_owned()).or_insert(0) += 1;
+                }
+                Some(_) => (),
+                None => problems.push(Problem::error(format!(
+                    "{}: record {} has no KKOD",
+                    file_def.name, index
+                ))),
+            }
+        }
+
+        for (kkod, count) in unknown {
+            problems.push(Problem::warning(format!(
+                "{}: kkod {} is not in the config ({} records)",
+                file_def.name, kkod, count
+            )));
+        }
+    }
+    Ok(problems)
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        match self.position {
+            Some(p) => write!(f, "{}:{}: {}: {}", p.line, p.column, severity, self.message),
+            None => write!(f, "{}: {}", severity, self.message),
+        }
+    }
+}
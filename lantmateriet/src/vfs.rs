@@ -0,0 +1,212 @@
+//! Files of a delivery, either in a folder or in the zip archive from lantmäteriet's FTP.
+//!
+//! Everything in a zip archive is treated as being in one folder, folders inside the archive and
+//! nested zip archives are flattened. Entries in zip archives are read into memory when opened.
+
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Cursor, ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+/// Nested zip archives deeper than this are ignored.
+const MAX_DEPTH: usize = 4;
+
+/// A folder or a zip archive with shape files.
+#[derive(Debug)]
+pub enum Source {
+    Folder(PathBuf),
+    Zip(Archive),
+}
+
+#[derive(Debug)]
+pub struct Archive {
+    path: PathBuf,
+    entries: Vec<Entry>,
+}
+
+/// A file in a zip archive.
+#[derive(Debug)]
+struct Entry {
+    /// File name without folders.
+    name: String,
+    /// Entry names from the outermost archive to the file, more than one for nested archives.
+    path: Vec<String>,
+}
+
+/// An opened file, on disk or in memory.
+pub struct VirtualFile(Box<dyn ReadSeek>);
+
+trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+impl Source {
+    /// Opens a folder, or a zip archive if `path` is a file ending with `.zip`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Source> {
+        let path = path.as_ref();
+        if path.is_file() && has_extension(&path.to_string_lossy(), "zip") {
+            Ok(Source::Zip(Archive::open(path)?))
+        } else {
+            Ok(Source::Folder(path.to_path_buf()))
+        }
+    }
+
+    /// Path of the folder or zip archive.
+    pub fn path(&self) -> &Path {
+        match self {
+            Source::Folder(path) => path,
+            Source::Zip(archive) => &archive.path,
+        }
+    }
+
+    /// Names of all files, non UTF-8 names are left out.
+    pub fn file_names(&self) -> io::Result<Vec<String>> {
+        match self {
+            Source::Folder(path) => {
+                let entries = fs::read_dir(path).map_err(|e| {
+                    io::Error::new(e.kind(), format!("Could not read folder {:?}: {}", path, e))
+                })?;
+                let mut names = Vec::new();
+                for entry in entries {
+                    if let Some(name) = entry?.file_name().to_str() {
+                        names.push(name.to_owned());
+                    }
+                }
+                Ok(names)
+            }
+            Source::Zip(archive) => Ok(archive.entries.iter().map(|e| e.name.clone()).collect()),
+        }
+    }
+
+    /// Opens a file by name, without regard to case if there is no exact match.
+    pub fn open_file(&self, name: &str) -> io::Result<VirtualFile> {
+        let name = self.find(name)?.unwrap_or_else(|| name.to_owned());
+        match self {
+            Source::Folder(path) => {
+                let file_path = path.join(&name);
+                match File::open(&file_path) {
+                    Ok(file) => Ok(VirtualFile(Box::new(file))),
+                    Err(ref e) if e.kind() == ErrorKind::NotFound => {
+                        Err(not_found(&format!("{:?}", file_path)))
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            Source::Zip(archive) => archive.open_file(&name),
+        }
+    }
+
+    /// Name of the file matching `name`, an exact match is preferred.
+    fn find(&self, name: &str) -> io::Result<Option<String>> {
+        let names = self.file_names()?;
+        if names.iter().any(|n| n == name) {
+            return Ok(Some(name.to_owned()));
+        }
+        Ok(names.into_iter().find(|n| n.eq_ignore_ascii_case(name)))
+    }
+}
+
+impl Archive {
+    fn open(path: &Path) -> io::Result<Archive> {
+        let file = File::open(path)?;
+        let mut entries = Vec::new();
+        list_entries(file, &[], &mut entries).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Could not read zip archive {:?}: {}", path, e),
+            )
+        })?;
+        Ok(Archive {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    fn open_file(&self, name: &str) -> io::Result<VirtualFile> {
+        let entry = match self.entries.iter().find(|e| e.name == name) {
+            Some(entry) => entry,
+            None => return Err(not_found(&format!("{} in {:?}", name, self.path))),
+        };
+
+        let mut data = read_entry(File::open(&self.path)?, &entry.path[0])?;
+        for entry_name in &entry.path[1..] {
+            data = read_entry(Cursor::new(data), entry_name)?;
+        }
+        Ok(VirtualFile(Box::new(Cursor::new(data))))
+    }
+}
+
+/// Reads an entry of an archive into memory.
+fn read_entry<R: Read + Seek>(reader: R, entry_name: &str) -> io::Result<Vec<u8>> {
+    let mut archive = ZipArchive::new(reader).map_err(zip_error)?;
+    let mut file = archive.by_name(entry_name).map_err(zip_error)?;
+    let mut data = Vec::with_capacity(file.size() as usize);
+    file.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Adds all files in the archive to `entries`, and the files of nested archives.
+fn list_entries<R: Read + Seek>(
+    reader: R,
+    parents: &[String],
+    entries: &mut Vec<Entry>,
+) -> io::Result<()> {
+    let mut archive = ZipArchive::new(reader).map_err(zip_error)?;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).map_err(zip_error)?;
+        if file.is_dir() {
+            continue;
+        }
+
+        let entry_name = file.name().to_owned();
+        let name = entry_name.rsplit('/').next().unwrap_or("").to_owned();
+        let mut path = parents.to_vec();
+        path.push(entry_name);
+
+        if has_extension(&name, "zip") && parents.len() < MAX_DEPTH {
+            let mut data = Vec::with_capacity(file.size() as usize);
+            file.read_to_end(&mut data)?;
+            list_entries(Cursor::new(data), &path, entries)?;
+        } else if !name.is_empty() {
+            entries.push(Entry { name, path });
+        }
+    }
+    Ok(())
+}
+
+fn has_extension(name: &str, extension: &str) -> bool {
+    name.rsplit_once('.')
+        .is_some_and(|(_, e)| e.eq_ignore_ascii_case(extension))
+}
+
+fn not_found(what: &str) -> io::Error {
+    io::Error::new(
+        ErrorKind::NotFound,
+        format!("Could not open shape file {}, file not found.", what),
+    )
+}
+
+fn zip_error(e: zip::result::ZipError) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, e.to_string())
+}
+
+impl Read for VirtualFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Seek for VirtualFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+impl fmt::Debug for VirtualFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "VirtualFile")
+    }
+}